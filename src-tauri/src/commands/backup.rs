@@ -0,0 +1,18 @@
+use crate::core::backup::BackupRun;
+use crate::core::UndoSummary;
+use crate::error::AppResult;
+use std::path::PathBuf;
+
+/// Starts an in-place run whose originals are kept under `backup_root`.
+/// Returns the run folder to pass as `inPlace.backupDir` and to `undo_run`.
+#[tauri::command]
+pub async fn begin_in_place_run(backup_root: String) -> AppResult<String> {
+    let run = BackupRun::create(&PathBuf::from(backup_root))?;
+    Ok(run.dir().to_string_lossy().to_string())
+}
+
+/// Restores the originals replaced by an in-place run
+#[tauri::command]
+pub async fn undo_run(run_dir: String) -> AppResult<UndoSummary> {
+    BackupRun::open(&PathBuf::from(run_dir))?.undo()
+}
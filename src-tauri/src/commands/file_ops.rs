@@ -1,64 +1,204 @@
-use crate::core::FileInfo;
-use crate::error::AppResult;
+use crate::commands::preview::{open_thumbnails, THUMBNAIL_SIZE};
+use crate::core::format::{detect_format, extension_matches, format_name, header_reader};
+use crate::core::inspect::inspect_image;
+use crate::core::svg::{is_svg, svg_dimensions};
+use crate::core::{FileInfo, ImageDetails, ScanOptions, SvgOptions, SymlinkPolicy};
+use crate::error::{AppError, AppResult, ResultExt};
+use ignore::overrides::OverrideBuilder;
+use ignore::WalkBuilder;
+use image::ImageFormat;
 use std::path::{Path, PathBuf};
-use walkdir::WalkDir;
 
-const SUPPORTED_EXTENSIONS: &[&str] = &["png", "jpg", "jpeg", "webp", "bmp", "tiff", "gif"];
+const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "webp", "bmp", "tiff", "gif", "svg", "svgz",
+];
+
+const SUPPORTED_FORMATS: &[ImageFormat] = &[
+    ImageFormat::Png,
+    ImageFormat::Jpeg,
+    ImageFormat::WebP,
+    ImageFormat::Bmp,
+    ImageFormat::Tiff,
+    ImageFormat::Gif,
+];
+
+// Tool and OS folders that never contain assets worth optimizing
+const SYSTEM_FOLDERS: &[&str] = &[
+    ".git",
+    ".svn",
+    ".hg",
+    "node_modules",
+    "__MACOSX",
+    "$RECYCLE.BIN",
+    "System Volume Information",
+];
 
 #[tauri::command]
 pub async fn scan_directory(
     path: String,
     recursive: bool,
     max_depth: Option<usize>,
-) -> Result<Vec<FileInfo>, ()> {
+    options: Option<ScanOptions>,
+    app_handle: tauri::AppHandle,
+) -> AppResult<Vec<FileInfo>> {
     let path = PathBuf::from(path);
+    let options = options.unwrap_or_default();
 
     if !path.exists() {
-        return Err(());
+        return Err(AppError::FileNotFound(path.to_string_lossy().to_string()));
     }
 
+    // Surface an unreadable root instead of returning an empty list
+    std::fs::read_dir(&path).with_path(&path)?;
+
+    let walker = build_walker(&path, recursive, max_depth, &options)?;
+
     let mut files = Vec::new();
-    let walker = if recursive {
-        WalkDir::new(&path).max_depth(max_depth.unwrap_or(10))
-    } else {
-        WalkDir::new(&path).max_depth(1)
-    };
+    for entry in walker.filter_map(|e| e.ok()) {
+        if !is_scannable_file(&entry, &options.symlinks) {
+            continue;
+        }
+
+        // Only sniff files that look like images by name or have no extension
+        let is_candidate = match entry.path().extension() {
+            Some(ext) => {
+                SUPPORTED_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())
+            }
+            None => true,
+        };
+        if !is_candidate {
+            continue;
+        }
+
+        match detect_format(entry.path()) {
+            Ok(Some(format)) if SUPPORTED_FORMATS.contains(&format) => {}
+            Ok(None) if is_svg(entry.path()) => {}
+            _ => continue,
+        }
 
-    for entry in walker.into_iter().filter_map(|e| e.ok()) {
-        if entry.file_type().is_file() {
-            if let Some(ext) = entry.path().extension() {
-                if SUPPORTED_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()) {
-                    if let Ok(file_info) = get_file_info(entry.path()) {
-                        files.push(file_info);
-                    }
-                }
+        if let Ok(file_info) = get_file_info(entry.path()) {
+            if matches_filters(&file_info, &options) {
+                files.push(file_info);
             }
         }
     }
 
+    // Warm the thumbnail cache in the background so the file list shows
+    // thumbnails right away
+    if let Some(cache) = open_thumbnails(&app_handle) {
+        let paths: Vec<PathBuf> = files.iter().map(|f| PathBuf::from(&f.path)).collect();
+        std::thread::spawn(move || cache.pregenerate(&paths, THUMBNAIL_SIZE));
+    }
+
     Ok(files)
 }
 
+fn build_walker(
+    root: &Path,
+    recursive: bool,
+    max_depth: Option<usize>,
+    options: &ScanOptions,
+) -> AppResult<ignore::Walk> {
+    let mut overrides = OverrideBuilder::new(root);
+    for pattern in &options.include {
+        overrides.add(pattern).map_err(invalid_glob)?;
+    }
+    for pattern in &options.exclude {
+        overrides
+            .add(&format!("!{}", pattern))
+            .map_err(invalid_glob)?;
+    }
+    let overrides = overrides.build().map_err(invalid_glob)?;
+
+    let mut builder = WalkBuilder::new(root);
+    builder
+        .max_depth(Some(if recursive {
+            max_depth.unwrap_or(10)
+        } else {
+            1
+        }))
+        .overrides(overrides)
+        .hidden(options.skip_hidden)
+        .ignore(options.respect_ignore_files)
+        .git_ignore(options.respect_ignore_files)
+        .git_exclude(options.respect_ignore_files)
+        .git_global(false)
+        .require_git(false)
+        .parents(options.respect_ignore_files)
+        // Loops are reported as errors by the walker and skipped below
+        .follow_links(options.symlinks == SymlinkPolicy::Follow);
+
+    if options.skip_system_folders {
+        builder.filter_entry(|entry| {
+            let is_dir = entry.file_type().is_some_and(|t| t.is_dir());
+            !(is_dir && SYSTEM_FOLDERS.contains(&entry.file_name().to_string_lossy().as_ref()))
+        });
+    }
+
+    Ok(builder.build())
+}
+
+fn invalid_glob(err: ignore::Error) -> AppError {
+    AppError::InvalidConfig(format!("Invalid glob pattern: {}", err))
+}
+
+fn is_scannable_file(entry: &ignore::DirEntry, symlinks: &SymlinkPolicy) -> bool {
+    let Some(file_type) = entry.file_type() else {
+        return false;
+    };
+
+    if file_type.is_symlink() {
+        // Only reachable when links aren't followed by the walker
+        return *symlinks == SymlinkPolicy::FilesOnly
+            && std::fs::metadata(entry.path()).is_ok_and(|m| m.is_file());
+    }
+
+    file_type.is_file()
+}
+
+fn matches_filters(info: &FileInfo, options: &ScanOptions) -> bool {
+    let within = |value: u64, min: Option<u64>, max: Option<u64>| {
+        min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+    };
+    let to_u64 = |v: Option<u32>| v.map(u64::from);
+
+    within(info.size, options.min_file_size, options.max_file_size)
+        && within(
+            info.width as u64,
+            to_u64(options.min_width),
+            to_u64(options.max_width),
+        )
+        && within(
+            info.height as u64,
+            to_u64(options.min_height),
+            to_u64(options.max_height),
+        )
+}
+
 #[tauri::command]
-pub async fn get_file_info_command(path: String) -> Result<FileInfo, ()> {
-    get_file_info(Path::new(&path)).map_err(|_| ())
+pub async fn get_file_info_command(path: String) -> AppResult<FileInfo> {
+    get_file_info(Path::new(&path))
 }
 
 fn get_file_info(path: &Path) -> AppResult<FileInfo> {
-    let metadata = std::fs::metadata(path)?;
+    let metadata = std::fs::metadata(path).with_path(path)?;
     let size = metadata.len();
 
+    let extension = crate::utils::get_file_extension(path);
+
+    // Sniffed only when no raster signature matches, as in scan_directory
+    let detected = detect_format(path)?;
+    if detected.is_none() && is_svg(path) {
+        return get_svg_info(path, size, extension);
+    }
+
     // Fast dimension reading - only read image headers, don't decode full image
-    let (width, height, format) = match get_image_dimensions_fast(path) {
-        Ok((w, h)) => {
-            let fmt = path
-                .extension()
-                .and_then(|s| s.to_str())
-                .unwrap_or("unknown")
-                .to_uppercase();
-            (w, h, fmt)
-        }
-        Err(_) => (0, 0, "unknown".to_string()),
+    let (width, height) = get_image_dimensions_fast(path).unwrap_or((0, 0));
+
+    let format = match (detected, &extension) {
+        (Some(detected), _) => format_name(detected),
+        (None, Some(ext)) => ext.to_uppercase(),
+        (None, None) => "unknown".to_string(),
     };
 
     Ok(FileInfo {
@@ -72,26 +212,56 @@ fn get_file_info(path: &Path) -> AppResult<FileInfo> {
         width,
         height,
         format,
+        detected_format: detected.map(format_name),
+        extension_mismatch: detected
+            .is_some_and(|detected| !extension_matches(extension.as_deref(), detected)),
+        extension,
+        details: detected
+            .map(|format| inspect_image(path, format))
+            .unwrap_or_default(),
+    })
+}
+
+fn get_svg_info(path: &Path, size: u64, extension: Option<String>) -> AppResult<FileInfo> {
+    let (width, height) = svg_dimensions(path, &SvgOptions::default())?;
+
+    Ok(FileInfo {
+        path: path.to_string_lossy().to_string(),
+        name: path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .unwrap_or("unknown")
+            .to_string(),
+        size,
+        width,
+        height,
+        format: "SVG".to_string(),
+        detected_format: Some("SVG".to_string()),
+        extension_mismatch: !matches!(extension.as_deref(), Some("svg" | "svgz")),
+        extension,
+        // Vector documents render onto a transparent canvas
+        details: ImageDetails {
+            has_alpha: true,
+            ..ImageDetails::default()
+        },
     })
 }
 
 // Fast dimension reading - only reads image headers
 fn get_image_dimensions_fast(path: &Path) -> Result<(u32, u32), Box<dyn std::error::Error>> {
-    let file = std::fs::File::open(path)?;
-    let reader = std::io::BufReader::new(file);
-
     // Use ImageReader for fast header-only reading
-    let reader = image::ImageReader::new(reader).with_guessed_format()?;
-    let dimensions = reader.into_dimensions()?;
+    let dimensions = header_reader(path)?.into_dimensions()?;
     Ok(dimensions)
 }
 
 #[tauri::command]
-pub async fn open_output_folder(file_path: String) -> Result<(), String> {
+pub async fn open_output_folder(file_path: String) -> AppResult<()> {
     let path = PathBuf::from(&file_path);
 
     // Get the parent directory
-    let folder = path.parent().ok_or("Failed to get parent directory")?;
+    let folder = path.parent().ok_or_else(|| {
+        AppError::InvalidConfig("Failed to get parent directory".to_string()).with_path(&path)
+    })?;
 
     // Open the folder in file explorer
     #[cfg(target_os = "linux")]
@@ -99,7 +269,7 @@ pub async fn open_output_folder(file_path: String) -> Result<(), String> {
         std::process::Command::new("xdg-open")
             .arg(folder)
             .spawn()
-            .map_err(|e| format!("Failed to open folder: {}", e))?;
+            .with_path(folder)?;
     }
 
     #[cfg(target_os = "windows")]
@@ -107,7 +277,7 @@ pub async fn open_output_folder(file_path: String) -> Result<(), String> {
         std::process::Command::new("explorer")
             .arg(folder)
             .spawn()
-            .map_err(|e| format!("Failed to open folder: {}", e))?;
+            .with_path(folder)?;
     }
 
     #[cfg(target_os = "macos")]
@@ -115,7 +285,7 @@ pub async fn open_output_folder(file_path: String) -> Result<(), String> {
         std::process::Command::new("open")
             .arg(folder)
             .spawn()
-            .map_err(|e| format!("Failed to open folder: {}", e))?;
+            .with_path(folder)?;
     }
 
     Ok(())
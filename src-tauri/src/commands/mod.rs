@@ -1,9 +1,13 @@
+pub mod backup;
 pub mod file_ops;
 pub mod preview;
 pub mod process;
+pub mod protocol;
 pub mod window;
 
+pub use backup::*;
 pub use file_ops::*;
 pub use preview::*;
 pub use process::*;
+pub use protocol::*;
 pub use window::*;
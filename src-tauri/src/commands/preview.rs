@@ -1,17 +1,210 @@
-use crate::core::pipeline::generate_preview;
-use std::path::PathBuf;
+use crate::core::compare::compare_images;
+use crate::core::format::open_image;
+use crate::core::output::output_mime_type;
+use crate::core::pipeline::{compression_ratio, encode_image_blocking, load_preview};
+use crate::core::scheduler::spawn_blocking;
+use crate::core::thumbnail::{ThumbnailCache, MAX_CACHE_BYTES};
+use crate::core::{CompressionPreview, DecodeLimits, ImageComparison, ProcessConfig};
+use crate::error::{AppError, AppResult, ResultExt};
+use base64::Engine;
+use std::path::{Path, PathBuf};
+use tauri::Manager;
 
+/// Longest side of the previews shown in the preview dialog
+pub const PREVIEW_SIZE: u32 = 800;
+
+/// Longest side of the thumbnails shown in the file list
+pub const THUMBNAIL_SIZE: u32 = 128;
+
+// Errors are multiplied by this before coloring, so an error of 64 out of
+// 255 already shows at full heat
+const DEFAULT_AMPLIFY: f32 = 4.0;
+
+// Thumbnails live in the app cache directory, next to the process cache
+pub fn open_thumbnails(app_handle: &tauri::AppHandle) -> Option<ThumbnailCache> {
+    let dir = app_handle.path().app_cache_dir().ok()?.join("thumbnails");
+    ThumbnailCache::open(dir, MAX_CACHE_BYTES).ok()
+}
+
+/// Base64 preview, JPEG or WebP for transparent images. The webview loads
+/// previews through the `preview://` protocol instead, which avoids the
+/// base64 round trip and lets it cache them.
 #[tauri::command]
-pub async fn generate_image_preview(path: String, max_size: Option<u32>) -> Result<String, String> {
+pub async fn generate_image_preview(
+    path: String,
+    max_size: Option<u32>,
+    limits: Option<DecodeLimits>,
+    app_handle: tauri::AppHandle,
+) -> AppResult<String> {
     let path = PathBuf::from(&path);
+    let max_size = max_size.unwrap_or(PREVIEW_SIZE);
+    let limits = limits.unwrap_or_else(DecodeLimits::preview);
 
-    let img = image::open(&path).map_err(|e| e.to_string())?;
+    let data = match open_thumbnails(&app_handle) {
+        Some(cache) => cache.get_or_create(&path, max_size, &limits)?.data,
+        None => load_preview(&path, max_size, &limits)?,
+    };
 
-    generate_preview(&img, max_size.unwrap_or(800)).map_err(|e| e.to_string())
+    Ok(base64::engine::general_purpose::STANDARD.encode(data))
 }
 
 // Alias for frontend compatibility
 #[tauri::command]
-pub async fn get_image_preview(file_path: String) -> Result<String, String> {
-    generate_image_preview(file_path, Some(800)).await
+pub async fn get_image_preview(
+    file_path: String,
+    app_handle: tauri::AppHandle,
+) -> AppResult<String> {
+    generate_image_preview(file_path, Some(PREVIEW_SIZE), None, app_handle).await
+}
+
+/// Deletes all cached thumbnails and returns the number of bytes freed
+#[tauri::command]
+pub async fn clear_thumbnail_cache(app_handle: tauri::AppHandle) -> AppResult<u64> {
+    match open_thumbnails(&app_handle) {
+        Some(cache) => cache.clear(),
+        None => Ok(0),
+    }
+}
+
+/// Runs the whole pipeline in memory with the given settings and reports
+/// the result, without writing anything to disk. With `compare`, the
+/// encoded bytes are also compared with the original, as `compare_output`
+/// would, without encoding a second time.
+#[tauri::command]
+pub async fn preview_compression(
+    path: String,
+    config: ProcessConfig,
+    max_size: Option<u32>,
+    compare: Option<bool>,
+) -> AppResult<CompressionPreview> {
+    // Decoding and encoding would stall the async runtime
+    spawn_blocking(move || {
+        let max_size = max_size.unwrap_or(PREVIEW_SIZE);
+        preview_blocking(
+            &PathBuf::from(&path),
+            &config,
+            max_size,
+            compare.unwrap_or(false),
+        )
+    })
+    .await
+}
+
+fn preview_blocking(
+    path: &Path,
+    config: &ProcessConfig,
+    max_size: u32,
+    compare: bool,
+) -> AppResult<CompressionPreview> {
+    let original_size = std::fs::metadata(path).with_path(path)?.len();
+    let encoded = encode_image_blocking(path, config, |_| {}).with_path(path)?;
+    let new_size = encoded.data.len() as u64;
+    let format = encoded.format_choice.as_ref().map_or_else(
+        || config.output_format.clone(),
+        |choice| choice.format.clone(),
+    );
+
+    let exact = encoded.width.max(encoded.height) <= max_size;
+    let processed = if compare || !exact {
+        Some(image::load_from_memory(&encoded.data)?)
+    } else {
+        None
+    };
+
+    let comparison = match &processed {
+        Some(processed) if compare => {
+            let original = open_image(path, &config.limits)?;
+            Some(compare_images(
+                &original,
+                processed,
+                DEFAULT_AMPLIFY,
+                max_size,
+            )?)
+        }
+        _ => None,
+    };
+
+    // Small outputs are shown as is; larger ones are scaled down, keeping
+    // the compression artifacts in a lossless PNG
+    let (preview, mime_type) = match processed {
+        Some(processed) if !exact => {
+            let img = processed.resize(max_size, max_size, image::imageops::FilterType::Lanczos3);
+            let mut png_data = Vec::new();
+            img.write_to(
+                &mut std::io::Cursor::new(&mut png_data),
+                image::ImageFormat::Png,
+            )?;
+            (png_data, "image/png")
+        }
+        _ => (encoded.data, output_mime_type(&format)),
+    };
+
+    Ok(CompressionPreview {
+        preview: base64::engine::general_purpose::STANDARD.encode(preview),
+        mime_type: mime_type.to_string(),
+        exact,
+        original_size,
+        new_size,
+        compression_ratio: compression_ratio(original_size, new_size),
+        width: encoded.width,
+        height: encoded.height,
+        format,
+        format_choice: encoded.format_choice,
+        warnings: encoded.warnings,
+        comparison,
+    })
+}
+
+/// Compares an input with its processed version, either an output file or
+/// an in-memory encoding with the given settings, and returns a difference
+/// heatmap with error statistics. With both, the output file is compared
+/// and the settings only supply the decode limits.
+#[tauri::command]
+pub async fn compare_output(
+    path: String,
+    output_path: Option<String>,
+    config: Option<ProcessConfig>,
+    amplify: Option<f32>,
+    max_size: Option<u32>,
+) -> AppResult<ImageComparison> {
+    spawn_blocking(move || {
+        let amplify = amplify.unwrap_or(DEFAULT_AMPLIFY);
+        let max_size = max_size.unwrap_or(PREVIEW_SIZE);
+        compare_blocking(
+            &PathBuf::from(&path),
+            output_path,
+            config,
+            amplify,
+            max_size,
+        )
+    })
+    .await
+}
+
+fn compare_blocking(
+    path: &Path,
+    output_path: Option<String>,
+    config: Option<ProcessConfig>,
+    amplify: f32,
+    max_size: u32,
+) -> AppResult<ImageComparison> {
+    let limits = config
+        .as_ref()
+        .map_or_else(DecodeLimits::preview, |config| config.limits.clone());
+    let original = open_image(path, &limits)?;
+
+    let processed = match (output_path, config) {
+        (Some(output_path), _) => open_image(&PathBuf::from(output_path), &limits)?,
+        (None, Some(config)) => {
+            let encoded = encode_image_blocking(path, &config, |_| {}).with_path(path)?;
+            image::load_from_memory(&encoded.data)?
+        }
+        (None, None) => {
+            return Err(AppError::InvalidConfig(
+                "Comparison needs an output file or settings to encode with".to_string(),
+            ))
+        }
+    };
+
+    compare_images(&original, &processed, amplify, max_size)
 }
@@ -1,57 +1,40 @@
-use crate::core::{process_image, FileConflictMode, ProcessConfig, ProcessResult};
-use rayon::prelude::*;
+use crate::core::output::{file_config, skipped_result};
+use crate::core::scheduler::{estimate_memory, BatchScheduler};
+use crate::core::{
+    encode_image_blocking, process_image, supports_animation, write_encoded, BatchOptions,
+    BatchSummary, CacheKey, ProcessCache, ProcessConfig, ProcessResult,
+};
+use crate::error::{AppError, AppResult, ErrorCode, ResultExt};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use tauri::Manager;
+
+// Open the incremental cache when the config asks for it. In-place runs
+// replace their inputs, so a cached result would point at the original.
+fn open_cache(app_handle: &tauri::AppHandle, config: &ProcessConfig) -> Option<ProcessCache> {
+    if !config.incremental || config.in_place.is_some() {
+        return None;
+    }
+    open_process_cache(app_handle)
+}
 
-// Helper function to resolve output path based on conflict mode
-fn resolve_output_path(
-    base_path: PathBuf,
-    conflict_mode: &FileConflictMode,
-) -> Result<Option<PathBuf>, String> {
-    match conflict_mode {
-        FileConflictMode::Overwrite => Ok(Some(base_path)),
-        FileConflictMode::Skip => {
-            if base_path.exists() {
-                Ok(None) // Skip this file
-            } else {
-                Ok(Some(base_path))
-            }
-        }
-        FileConflictMode::Rename => {
-            if !base_path.exists() {
-                return Ok(Some(base_path));
-            }
+fn open_process_cache(app_handle: &tauri::AppHandle) -> Option<ProcessCache> {
+    let dir = app_handle
+        .path()
+        .app_cache_dir()
+        .ok()?
+        .join("process-cache");
+    ProcessCache::open(dir).ok()
+}
 
-            let stem = base_path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .ok_or("Invalid filename")?;
-            let ext = base_path
-                .extension()
-                .and_then(|s| s.to_str())
-                .ok_or("Invalid extension")?;
-            let parent = base_path.parent().ok_or("Invalid parent directory")?;
-
-            let mut counter = 1;
-            loop {
-                let new_path = parent.join(format!(
-                    "{}_({}){}",
-                    stem,
-                    counter,
-                    if ext.is_empty() {
-                        String::new()
-                    } else {
-                        format!(".{}", ext)
-                    }
-                ));
-                if !new_path.exists() {
-                    return Ok(Some(new_path));
-                }
-                counter += 1;
-                if counter > 1000 {
-                    return Err("Too many conflicting files".to_string());
-                }
-            }
-        }
+/// Forgets every earlier run, so the next one processes all files again.
+/// Returns the number of bytes freed.
+#[tauri::command]
+pub async fn clear_process_cache(app_handle: tauri::AppHandle) -> AppResult<u64> {
+    match open_process_cache(&app_handle) {
+        Some(cache) => cache.clear(),
+        None => Ok(0),
     }
 }
 
@@ -59,131 +42,179 @@ fn resolve_output_path(
 pub async fn process_single_image(
     input_path: String,
     config: ProcessConfig,
-) -> Result<ProcessResult, String> {
+    app_handle: tauri::AppHandle,
+) -> AppResult<ProcessResult> {
     let path = PathBuf::from(&input_path);
 
-    // Generate output filename
-    let input_filename = path
-        .file_stem()
-        .and_then(|s| s.to_str())
-        .unwrap_or("output");
-
-    let ext = match config.output_format {
-        crate::core::OutputFormat::Png => "png",
-        crate::core::OutputFormat::Jpeg => "jpg",
-        crate::core::OutputFormat::WebP => "webp",
-    };
-
-    let output_filename = format!("{}_optimized.{}", input_filename, ext);
-
-    // Calculate output path with folder structure preservation
-    let base_output_path = if config.preserve_folder_structure {
-        if let Some(base_path) = &config.base_path {
-            // Calculate relative path from base_path
-            if let Ok(relative) = path.strip_prefix(base_path) {
-                // Get the parent directory of the file (relative to base)
-                if let Some(parent) = relative.parent() {
-                    // Combine output_path + relative_parent + filename
-                    config.output_path.join(parent).join(output_filename)
-                } else {
-                    // File is directly in base_path
-                    config.output_path.join(output_filename)
-                }
-            } else {
-                // Fallback if strip_prefix fails
-                config.output_path.join(output_filename)
-            }
-        } else {
-            // No base_path, use default
-            config.output_path.join(output_filename)
+    // Reuse the previous output if neither the input nor the settings changed
+    let cache = open_cache(&app_handle, &config);
+    let cache_key = cache
+        .as_ref()
+        .and_then(|_| CacheKey::new(&path, &config).ok());
+    if let (Some(cache), Some(key)) = (&cache, &cache_key) {
+        if let Some(result) = cache.lookup(key) {
+            return Ok(result);
         }
-    } else {
-        // Don't preserve structure
-        config.output_path.join(output_filename)
-    };
+    }
 
-    // Resolve conflicts
-    let output_path = match resolve_output_path(base_output_path, &config.file_conflict_mode)? {
-        Some(path) => path,
-        None => {
-            // File skipped
-            return Ok(ProcessResult {
-                success: true,
-                original_size: 0,
-                new_size: 0,
-                compression_ratio: 0.0,
-                output_path: String::from("Skipped (file exists)"),
-                error: None,
-            });
-        }
+    let Some(file_config) = file_config(&path, &config)? else {
+        return Ok(skipped_result());
     };
 
-    // Create output directory if it doesn't exist
-    if let Some(parent) = output_path.parent() {
-        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
-    }
+    let result = process_image(&path, file_config, None).await?;
 
-    // Update config with full output path
-    let mut file_config = config.clone();
-    file_config.output_path = output_path;
+    if let (Some(cache), Some(key)) = (&cache, &cache_key) {
+        let _ = cache.record(key, &result);
+    }
 
-    process_image(&path, file_config, None)
-        .await
-        .map_err(|e| e.to_string())
+    Ok(result)
 }
 
+/// Processes many images at once. Reading, encoding and writing are
+/// scheduled separately, and encodes only start while their estimated
+/// memory fits the batch's budget. Once the output disk fills up, files
+/// that haven't started writing are failed instead of encoded.
 #[tauri::command]
 pub async fn process_batch_images(
     input_paths: Vec<String>,
     config: ProcessConfig,
-    _app_handle: tauri::AppHandle,
-) -> Result<Vec<ProcessResult>, String> {
-    let paths: Vec<PathBuf> = input_paths.iter().map(PathBuf::from).collect();
-
-    // Process in parallel using rayon
-    let results: Vec<ProcessResult> = paths
-        .par_iter()
-        .enumerate()
-        .map(|(_index, path)| {
-            // Create unique output path for each file
-            let mut file_config = config.clone();
-            let output_name = path
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or("output");
-
-            let ext = match config.output_format {
-                crate::core::OutputFormat::Png => "png",
-                crate::core::OutputFormat::Jpeg => "jpg",
-                crate::core::OutputFormat::WebP => "webp",
-            };
-
-            file_config.output_path = config
-                .output_path
-                .join(format!("{}_optimized.{}", output_name, ext));
-
-            // Process image
-            let result = tokio::runtime::Handle::current()
-                .block_on(async { process_image(path, file_config, None).await });
-
-            // TODO: Emit progress event to frontend
-            // let _ = app_handle.emit("process-progress", serde_json::json!({
-            //     "index": index,
-            //     "total": paths.len(),
-            //     "file": path.to_string_lossy(),
-            //     "success": result.is_ok(),
-            // }));
-
-            result.unwrap_or_else(|e| ProcessResult {
-                success: false,
-                original_size: 0,
-                new_size: 0,
-                compression_ratio: 0.0,
-                output_path: String::new(),
-                error: Some(e.to_string()),
+    options: Option<BatchOptions>,
+    app_handle: tauri::AppHandle,
+) -> AppResult<BatchSummary> {
+    let scheduler = Arc::new(BatchScheduler::new(&options.unwrap_or_default()));
+    let cache = open_cache(&app_handle, &config).map(Arc::new);
+    let config = Arc::new(config);
+    let disk_full = Arc::new(AtomicBool::new(false));
+
+    // Every file gets a task up front; the scheduler decides when each
+    // stage may run
+    let tasks: Vec<_> = input_paths
+        .into_iter()
+        .map(|path| {
+            let path = PathBuf::from(path);
+            let task = process_batch_file(
+                path.clone(),
+                config.clone(),
+                cache.clone(),
+                scheduler.clone(),
+                disk_full.clone(),
+            );
+            let disk_full = disk_full.clone();
+            tokio::spawn(async move {
+                let result = task.await;
+                if matches!(&result, Err(e) if e.code() == ErrorCode::DiskFull) {
+                    disk_full.store(true, Ordering::SeqCst);
+                }
+                result.with_path(&path)
             })
         })
         .collect();
 
-    Ok(results)
+    let mut results = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        let result = task
+            .await
+            .map_err(|e| AppError::Processing(format!("Worker failed: {}", e)))
+            .and_then(|result| result);
+        results.push(result.unwrap_or_else(|e| failed_result(&e)));
+    }
+
+    let cached = results.iter().filter(|r| r.cached).count();
+    let failed = results.iter().filter(|r| !r.success).count();
+
+    Ok(BatchSummary {
+        processed: results.len() - cached - failed,
+        cached,
+        failed,
+        results,
+        stopped: disk_full.load(Ordering::SeqCst),
+    })
+}
+
+async fn process_batch_file(
+    path: PathBuf,
+    config: Arc<ProcessConfig>,
+    cache: Option<Arc<ProcessCache>>,
+    scheduler: Arc<BatchScheduler>,
+    disk_full: Arc<AtomicBool>,
+) -> AppResult<ProcessResult> {
+    // Read stage: hash for the cache and probe the header for the estimate
+    let (cache_key, original_size, memory) = {
+        let (path, config, cache) = (path.clone(), config.clone(), cache.clone());
+        scheduler
+            .run_io(move || {
+                let cache_key = cache
+                    .as_ref()
+                    .and_then(|_| CacheKey::new(&path, &config).ok());
+                let original_size = std::fs::metadata(&path).with_path(&path)?.len();
+                let memory = estimate_memory(&path, supports_animation(&config.output_format));
+                Ok((cache_key, original_size, memory))
+            })
+            .await?
+    };
+    if let (Some(cache), Some(key)) = (&cache, &cache_key) {
+        if let Some(result) = cache.lookup(key) {
+            return Ok(result);
+        }
+    }
+
+    // Pick the output path, resolving conflicts with existing files
+    let file_config = {
+        let (path, config) = (path.clone(), config.clone());
+        match scheduler
+            .run_io(move || file_config(&path, &config))
+            .await?
+        {
+            Some(file_config) => Arc::new(file_config),
+            None => return Ok(skipped_result()),
+        }
+    };
+
+    // Encode stage, on the blocking pool so the async runtime stays free
+    let encoded = {
+        let (file_config, disk_full) = (file_config.clone(), disk_full.clone());
+        scheduler
+            .run_cpu(memory, move || {
+                check_disk_space(&disk_full)?;
+                encode_image_blocking(&path, &file_config, |_| {})
+            })
+            .await?
+    };
+
+    // Write stage
+    scheduler
+        .run_io(move || {
+            check_disk_space(&disk_full)?;
+            let result = write_encoded(encoded, &file_config, original_size)?;
+            if let (Some(cache), Some(key)) = (&cache, &cache_key) {
+                let _ = cache.record(key, &result);
+            }
+            Ok(result)
+        })
+        .await
+}
+
+// Fails fast once another file in the batch ran out of disk space
+fn check_disk_space(disk_full: &AtomicBool) -> AppResult<()> {
+    if disk_full.load(Ordering::SeqCst) {
+        return Err(AppError::DiskFull(
+            "Skipped after an earlier file couldn't be written".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn failed_result(error: &AppError) -> ProcessResult {
+    ProcessResult {
+        success: false,
+        original_size: 0,
+        new_size: 0,
+        compression_ratio: 0.0,
+        output_path: String::new(),
+        error: Some(error.to_info()),
+        cached: false,
+        unchanged: false,
+        warnings: Vec::new(),
+        format_choice: None,
+    }
 }
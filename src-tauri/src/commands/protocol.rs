@@ -0,0 +1,143 @@
+use crate::commands::preview::{open_thumbnails, PREVIEW_SIZE};
+use crate::core::config::DecodeLimits;
+use crate::core::pipeline::load_preview;
+use crate::core::scheduler::worker_count;
+use crate::core::thumbnail::bucket_size;
+use crate::error::{AppError, AppResult, ErrorCode};
+use percent_encoding::percent_decode_str;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use tauri::http::{header, HeaderValue, Request, Response, StatusCode};
+use tokio::sync::Semaphore;
+
+/// Scheme previews are served from, as `preview://localhost/<encoded path>?size=256`.
+/// The frontend builds these URLs with `convertFileSrc(path, "preview")`.
+pub const PREVIEW_SCHEME: &str = "preview";
+
+// Previews decoded at once, as many as the batch's default worker pool.
+// A folder of lazy thumbnails queues here instead of decoding all at once.
+static PREVIEW_WORKERS: LazyLock<Semaphore> = LazyLock::new(|| Semaphore::new(worker_count(0)));
+
+/// Answers a preview request with a thumbnail of the file named in the URL
+/// path. Thumbnails come from the thumbnail cache and carry its key as an
+/// ETag, so the webview revalidates them instead of fetching them again.
+///
+/// Requests wait for one of a fixed number of workers, then run on the
+/// blocking pool so decoding doesn't stall the webview's event loop.
+pub async fn handle_preview_request(
+    app_handle: tauri::AppHandle,
+    request: Request<Vec<u8>>,
+) -> Response<Vec<u8>> {
+    let Ok(_permit) = PREVIEW_WORKERS.acquire().await else {
+        return text_response(StatusCode::SERVICE_UNAVAILABLE, "Preview workers shut down");
+    };
+    tauri::async_runtime::spawn_blocking(move || {
+        serve_preview(&app_handle, &request).unwrap_or_else(|e| error_response(&e))
+    })
+    .await
+    .unwrap_or_else(|e| text_response(StatusCode::INTERNAL_SERVER_ERROR, &e.to_string()))
+}
+
+fn error_response(e: &AppError) -> Response<Vec<u8>> {
+    let status = match e.code() {
+        ErrorCode::NotFound => StatusCode::NOT_FOUND,
+        ErrorCode::PermissionDenied => StatusCode::FORBIDDEN,
+        ErrorCode::InvalidConfig => StatusCode::BAD_REQUEST,
+        ErrorCode::DecodeFailed | ErrorCode::UnsupportedFormat => {
+            StatusCode::UNSUPPORTED_MEDIA_TYPE
+        }
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    text_response(status, &e.to_string())
+}
+
+fn text_response(status: StatusCode, message: &str) -> Response<Vec<u8>> {
+    let mut response = Response::new(message.as_bytes().to_vec());
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    response
+}
+
+fn serve_preview(
+    app_handle: &tauri::AppHandle,
+    request: &Request<Vec<u8>>,
+) -> AppResult<Response<Vec<u8>>> {
+    let uri = request.uri();
+    let path = percent_decode_str(uri.path().trim_start_matches('/'))
+        .decode_utf8()
+        .map_err(|_| AppError::InvalidConfig("Preview path isn't valid UTF-8".to_string()))?;
+    let path = PathBuf::from(path.as_ref());
+    if path.as_os_str().is_empty() {
+        return Err(AppError::InvalidConfig(
+            "Preview URL has no path".to_string(),
+        ));
+    }
+
+    let size = uri
+        .query()
+        .into_iter()
+        .flat_map(|query| query.split('&'))
+        .find_map(|pair| pair.strip_prefix("size="))
+        .and_then(|size| size.parse().ok())
+        .map_or(PREVIEW_SIZE, bucket_size);
+
+    // Without a cache directory previews still work, just uncached
+    let Some(cache) = open_thumbnails(app_handle) else {
+        let data = load_preview(&path, size, &DecodeLimits::preview())?;
+        return Ok(preview_response(data, None));
+    };
+
+    // The key only needs the file's metadata, so an unchanged preview is
+    // confirmed without touching the cache
+    let etag = format!("\"{}\"", cache.key(&path, size)?);
+    if etag_matches(request, &etag) {
+        let mut response = Response::new(Vec::new());
+        *response.status_mut() = StatusCode::NOT_MODIFIED;
+        set_cache_headers(&mut response, &etag);
+        return Ok(response);
+    }
+
+    let thumbnail = cache.get_or_create(&path, size, &DecodeLimits::preview())?;
+    let etag = format!("\"{}\"", thumbnail.key);
+    Ok(preview_response(thumbnail.data, Some(&etag)))
+}
+
+fn preview_response(data: Vec<u8>, etag: Option<&str>) -> Response<Vec<u8>> {
+    // Previews are JPEG, or WebP when the image has transparency
+    let mime_type = image::guess_format(&data)
+        .map(|format| format.to_mime_type())
+        .unwrap_or("application/octet-stream");
+
+    let mut response = Response::new(data);
+    response
+        .headers_mut()
+        .insert(header::CONTENT_TYPE, HeaderValue::from_static(mime_type));
+    if let Some(etag) = etag {
+        set_cache_headers(&mut response, etag);
+    }
+    response
+}
+
+// `no-cache` still stores the preview but checks the ETag before each use,
+// so edited files show up without cache busting on the frontend
+fn set_cache_headers(response: &mut Response<Vec<u8>>, etag: &str) {
+    let headers = response.headers_mut();
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+    if let Ok(value) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, value);
+    }
+}
+
+fn etag_matches(request: &Request<Vec<u8>>, etag: &str) -> bool {
+    request
+        .headers()
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == etag || tag == "*")
+}
@@ -1,33 +1,34 @@
+use crate::error::{AppError, AppResult};
 use tauri::Window;
 
 #[tauri::command]
-pub fn minimize_window(window: Window) -> Result<(), String> {
-    window.minimize().map_err(|e| e.to_string())
+pub fn minimize_window(window: Window) -> AppResult<()> {
+    window.minimize().map_err(AppError::from)
 }
 
 #[tauri::command]
-pub fn maximize_window(window: Window) -> Result<(), String> {
-    window.maximize().map_err(|e| e.to_string())
+pub fn maximize_window(window: Window) -> AppResult<()> {
+    window.maximize().map_err(AppError::from)
 }
 
 #[tauri::command]
-pub fn unmaximize_window(window: Window) -> Result<(), String> {
-    window.unmaximize().map_err(|e| e.to_string())
+pub fn unmaximize_window(window: Window) -> AppResult<()> {
+    window.unmaximize().map_err(AppError::from)
 }
 
 #[tauri::command]
-pub fn toggle_maximize(window: Window) -> Result<(), String> {
-    let is_maximized = window.is_maximized().map_err(|e| e.to_string())?;
+pub fn toggle_maximize(window: Window) -> AppResult<()> {
+    let is_maximized = window.is_maximized().map_err(AppError::from)?;
     if is_maximized {
-        window.unmaximize().map_err(|e| e.to_string())
+        window.unmaximize().map_err(AppError::from)
     } else {
-        window.maximize().map_err(|e| e.to_string())
+        window.maximize().map_err(AppError::from)
     }
 }
 
 #[tauri::command]
-pub fn close_window(window: Window) -> Result<(), String> {
-    window.close().map_err(|e| e.to_string())
+pub fn close_window(window: Window) -> AppResult<()> {
+    window.close().map_err(AppError::from)
 }
 
 #[tauri::command]
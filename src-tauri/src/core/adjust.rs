@@ -0,0 +1,145 @@
+use crate::core::config::{AdjustmentOptions, SharpenOptions};
+use image::{imageops, DynamicImage, RgbaImage};
+
+// Rec. 709 luma weights
+const LUMA: [f32; 3] = [0.2126, 0.7152, 0.0722];
+
+/// Applies sharpening and tone adjustments. Alpha is left untouched.
+/// Neutral settings return the image as is, without copying it.
+pub fn apply_adjustments(img: DynamicImage, options: &AdjustmentOptions) -> DynamicImage {
+    if is_identity(options) {
+        return img;
+    }
+
+    let has_alpha = img.color().has_alpha();
+    let mut rgba = img.into_rgba8();
+
+    if let Some(sharpen) = &options.sharpen {
+        rgba = unsharp_mask(&rgba, sharpen);
+    }
+
+    let lut = tone_curve(options);
+    let saturation = if options.grayscale {
+        0.0
+    } else {
+        1.0 + options.saturation.clamp(-100.0, 100.0) / 100.0
+    };
+
+    for pixel in rgba.pixels_mut() {
+        let [r, g, b] = [
+            lut[pixel[0] as usize],
+            lut[pixel[1] as usize],
+            lut[pixel[2] as usize],
+        ];
+
+        if saturation == 1.0 {
+            pixel[0] = to_u8(r);
+            pixel[1] = to_u8(g);
+            pixel[2] = to_u8(b);
+            continue;
+        }
+
+        // Move each channel towards (or away from) the pixel's luma
+        let luma = r * LUMA[0] + g * LUMA[1] + b * LUMA[2];
+        pixel[0] = to_u8(luma + (r - luma) * saturation);
+        pixel[1] = to_u8(luma + (g - luma) * saturation);
+        pixel[2] = to_u8(luma + (b - luma) * saturation);
+    }
+
+    restore_channels(DynamicImage::ImageRgba8(rgba), has_alpha)
+}
+
+/// Drops the alpha channel again from an image that was worked on in RGBA
+/// when the input had none. Encoders pick their color type from the image
+/// they get, so an opaque input would otherwise gain an alpha channel.
+pub fn restore_channels(img: DynamicImage, had_alpha: bool) -> DynamicImage {
+    if had_alpha || !img.color().has_alpha() {
+        img
+    } else {
+        DynamicImage::ImageRgb8(img.into_rgb8())
+    }
+}
+
+// Adds back `amount` times the difference from a Gaussian blur, skipping
+// differences below `threshold` so flat areas and noise stay smooth
+fn unsharp_mask(rgba: &RgbaImage, options: &SharpenOptions) -> RgbaImage {
+    let blurred = imageops::blur(rgba, options.radius.max(0.1));
+    let amount = options.amount.max(0.0) / 100.0;
+    let threshold = i32::from(options.threshold);
+
+    let mut sharpened = rgba.clone();
+    for (out, soft) in sharpened.pixels_mut().zip(blurred.pixels()) {
+        for c in 0..3 {
+            let diff = i32::from(out[c]) - i32::from(soft[c]);
+            if diff.abs() >= threshold {
+                out[c] = to_u8(f32::from(out[c]) + diff as f32 * amount);
+            }
+        }
+    }
+    sharpened
+}
+
+// Brightness, contrast and gamma act on each channel independently,
+// so they collapse into one lookup table
+fn tone_curve(options: &AdjustmentOptions) -> [f32; 256] {
+    let brightness = options.brightness.clamp(-100.0, 100.0) * 2.55;
+    let contrast = options.contrast.clamp(-100.0, 100.0) * 2.55;
+    let contrast_factor = (259.0 * (contrast + 255.0)) / (255.0 * (259.0 - contrast));
+    let inverse_gamma = 1.0 / options.gamma.clamp(0.1, 10.0);
+
+    let mut lut = [0.0; 256];
+    for (value, entry) in lut.iter_mut().enumerate() {
+        let v = value as f32 + brightness;
+        let v = (contrast_factor * (v - 128.0) + 128.0).clamp(0.0, 255.0);
+        *entry = 255.0 * (v / 255.0).powf(inverse_gamma);
+    }
+    lut
+}
+
+/// Whether any pixel is less than fully opaque
+pub fn has_transparency(img: &DynamicImage) -> bool {
+    match img {
+        DynamicImage::ImageRgba8(rgba) => rgba.pixels().any(|p| p[3] < 255),
+        DynamicImage::ImageLumaA8(la) => la.pixels().any(|p| p[1] < 255),
+        _ if img.color().has_alpha() => img.to_rgba8().pixels().any(|p| p[3] < 255),
+        _ => false,
+    }
+}
+
+/// Composites the image over a solid color and drops the alpha channel.
+/// Images without alpha are returned as is.
+pub fn flatten_alpha(img: DynamicImage, matte: [u8; 3]) -> DynamicImage {
+    if !img.color().has_alpha() {
+        return img;
+    }
+
+    let rgba = img.to_rgba8();
+    let flattened = image::RgbImage::from_fn(rgba.width(), rgba.height(), |x, y| {
+        let p = rgba.get_pixel(x, y);
+        let alpha = u32::from(p[3]);
+        let blend = |fg: u8, bg: u8| {
+            ((u32::from(fg) * alpha + u32::from(bg) * (255 - alpha) + 127) / 255) as u8
+        };
+        image::Rgb([
+            blend(p[0], matte[0]),
+            blend(p[1], matte[1]),
+            blend(p[2], matte[2]),
+        ])
+    });
+
+    DynamicImage::ImageRgb8(flattened)
+}
+
+/// Whether the options leave every pixel unchanged
+pub fn is_identity(options: &AdjustmentOptions) -> bool {
+    options.sharpen.is_none()
+        && options.brightness == 0.0
+        && options.contrast == 0.0
+        && options.saturation == 0.0
+        && options.gamma == 1.0
+        && !options.grayscale
+}
+
+fn to_u8(value: f32) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
@@ -0,0 +1,252 @@
+use crate::core::config::DecodeLimits;
+use crate::core::format::{check_limits, detect_format};
+use crate::error::{AppError, AppResult, ResultExt};
+use image::codecs::gif::GifDecoder;
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, DynamicImage, ImageDecoder, ImageFormat};
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// One fully composited frame; disposal and blending are already applied
+#[derive(Debug, Clone)]
+pub struct AnimationFrame {
+    pub image: DynamicImage,
+    pub delay_ms: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct Animation {
+    pub frames: Vec<AnimationFrame>,
+    /// Number of times to play, 0 means forever
+    pub loop_count: u32,
+}
+
+impl Animation {
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.frames
+            .first()
+            .map(|f| (f.image.width(), f.image.height()))
+            .unwrap_or((0, 0))
+    }
+
+    /// Applies the same transformation to every frame, keeping the timing.
+    /// Frames are moved into `f`, so it can return them unchanged for free.
+    pub fn map_frames<F>(self, mut f: F) -> AppResult<Animation>
+    where
+        F: FnMut(DynamicImage) -> AppResult<DynamicImage>,
+    {
+        let frames = self
+            .frames
+            .into_iter()
+            .map(|frame| {
+                Ok(AnimationFrame {
+                    image: f(frame.image)?,
+                    delay_ms: frame.delay_ms,
+                })
+            })
+            .collect::<AppResult<Vec<_>>>()?;
+
+        Ok(Animation {
+            frames,
+            loop_count: self.loop_count,
+        })
+    }
+}
+
+/// Decodes every frame of an animated GIF, APNG or WebP.
+/// Returns `None` for still images and other formats.
+pub fn decode_animation(path: &Path, limits: &DecodeLimits) -> AppResult<Option<Animation>> {
+    let Some(format) = detect_format(path)? else {
+        return Ok(None);
+    };
+
+    let open = || -> AppResult<BufReader<std::fs::File>> {
+        Ok(BufReader::new(std::fs::File::open(path).with_path(path)?))
+    };
+
+    // Every frame is composited onto a full canvas, so the canvas size
+    // decides what each one costs
+    let frames = match format {
+        ImageFormat::Gif => {
+            let decoder = GifDecoder::new(open()?).with_path(path)?;
+            check_canvas(&decoder, limits).with_path(path)?;
+            decoder.into_frames()
+        }
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(open()?).with_path(path)?;
+            if !decoder.is_apng().with_path(path)? {
+                return Ok(None);
+            }
+            check_canvas(&decoder, limits).with_path(path)?;
+            decoder.apng().with_path(path)?.into_frames()
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(open()?).with_path(path)?;
+            if !decoder.has_animation() {
+                return Ok(None);
+            }
+            check_canvas(&decoder, limits).with_path(path)?;
+            decoder.into_frames()
+        }
+        _ => return Ok(None),
+    };
+
+    // A small file can hold thousands of frames, so their total is capped too
+    let budget = limits.max_alloc_mb.saturating_mul(1024 * 1024);
+    let mut total: u64 = 0;
+    let mut decoded = Vec::new();
+    for frame in frames {
+        let frame = frame.with_path(path)?;
+        total += frame.buffer().as_raw().len() as u64;
+        if total > budget {
+            return Err(AppError::LimitExceeded(format!(
+                "Animation frames need more than {} MiB",
+                limits.max_alloc_mb
+            ))
+            .with_path(path));
+        }
+        decoded.push(frame);
+    }
+
+    if decoded.len() < 2 {
+        return Ok(None);
+    }
+
+    let frames = decoded
+        .into_iter()
+        .map(|frame| {
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            AnimationFrame {
+                delay_ms: numer / denom.max(1),
+                image: DynamicImage::ImageRgba8(frame.into_buffer()),
+            }
+        })
+        .collect();
+
+    let loop_count = match format {
+        ImageFormat::Gif => gif_loop_count(path),
+        ImageFormat::Png => open().ok().and_then(apng_loop_count),
+        ImageFormat::WebP => open().ok().and_then(webp_loop_count),
+        _ => None,
+    };
+    let loop_count = loop_count.unwrap_or(0);
+
+    Ok(Some(Animation { frames, loop_count }))
+}
+
+fn check_canvas(decoder: &impl ImageDecoder, limits: &DecodeLimits) -> AppResult<()> {
+    let (width, height) = decoder.dimensions();
+    check_limits(width, height, limits)
+}
+
+// The NETSCAPE2.0 extension precedes the first frame
+fn gif_loop_count(path: &Path) -> Option<u32> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut options = gif::DecodeOptions::new();
+    options.skip_frame_decoding(true);
+    let mut decoder = options.read_info(BufReader::new(file)).ok()?;
+    decoder.next_frame_info().ok()?;
+
+    match decoder.repeat() {
+        gif::Repeat::Infinite => Some(0),
+        // Browsers play once more than the stored count; no extension means once
+        gif::Repeat::Finite(count) => Some(u32::from(count) + 1),
+    }
+}
+
+// num_plays of the acTL chunk, which like the loop count uses 0 for forever
+fn apng_loop_count(reader: impl Read) -> Option<u32> {
+    let reader = png::Decoder::new(reader).read_info().ok()?;
+    let control = reader.info().animation_control?;
+    Some(control.num_plays)
+}
+
+// The ANIM chunk follows VP8X and the optional ICCP chunk, ahead of the
+// frames; its loop count also uses 0 for forever
+fn webp_loop_count(mut reader: impl Read + Seek) -> Option<u32> {
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header).ok()?;
+    if &header[..4] != b"RIFF" || &header[8..] != b"WEBP" {
+        return None;
+    }
+
+    loop {
+        let mut chunk = [0u8; 8];
+        reader.read_exact(&mut chunk).ok()?;
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]);
+        match &chunk[..4] {
+            b"ANIM" => {
+                // Background color, then the loop count
+                let mut anim = [0u8; 6];
+                reader.read_exact(&mut anim).ok()?;
+                return Some(u32::from(u16::from_le_bytes([anim[4], anim[5]])));
+            }
+            b"ANMF" => return None,
+            // Chunks are padded to an even size
+            _ => {
+                reader
+                    .seek(SeekFrom::Current(i64::from(size + size % 2)))
+                    .ok()?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    fn chunk(fourcc: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = fourcc.to_vec();
+        chunk.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(data);
+        if data.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn webp(chunks: &[Vec<u8>]) -> Cursor<Vec<u8>> {
+        let body: Vec<u8> = chunks.concat();
+        let mut data = b"RIFF".to_vec();
+        data.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+        data.extend_from_slice(b"WEBP");
+        data.extend_from_slice(&body);
+        Cursor::new(data)
+    }
+
+    #[test]
+    fn reads_webp_loop_count_after_other_chunks() {
+        let data = webp(&[
+            chunk(b"VP8X", &[0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            chunk(b"ICCP", &[1, 2, 3]),
+            chunk(b"ANIM", &[0, 0, 0, 0, 3, 0]),
+        ]);
+        assert_eq!(webp_loop_count(data), Some(3));
+    }
+
+    #[test]
+    fn webp_without_anim_chunk_has_no_loop_count() {
+        let data = webp(&[
+            chunk(b"VP8X", &[0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+            chunk(b"ANMF", &[0; 16]),
+        ]);
+        assert_eq!(webp_loop_count(data), None);
+    }
+
+    #[test]
+    fn reads_apng_plays() {
+        let mut data = Vec::new();
+        let mut encoder = png::Encoder::new(&mut data, 1, 1);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_animated(2, 5).unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&[0; 4]).unwrap();
+        writer.write_image_data(&[255; 4]).unwrap();
+        writer.finish().unwrap();
+
+        assert_eq!(apng_loop_count(Cursor::new(data)), Some(5));
+    }
+}
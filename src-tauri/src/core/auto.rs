@@ -0,0 +1,90 @@
+use crate::core::adjust::has_transparency;
+use crate::core::config::OutputFormat;
+use image::DynamicImage;
+use std::collections::HashSet;
+
+// Images with up to this many colors fit a lossless palette
+const PALETTE_COLORS: usize = 256;
+
+// Share of pixels matching their right-hand neighbour above which an image
+// counts as flat artwork (screenshots, UI, diagrams) even with many colors
+const FLAT_RATIO: f32 = 0.6;
+
+// Larger images are sampled on a grid to keep classification cheap
+const MAX_SAMPLES: u32 = 512 * 512;
+
+/// What the automatic format selection knows about an image
+#[derive(Debug, Clone)]
+pub struct ImageProfile {
+    pub has_alpha: bool,
+    /// Distinct colors, counted up to `PALETTE_COLORS + 1`
+    pub color_count: usize,
+    pub photographic: bool,
+}
+
+pub fn classify(img: &DynamicImage) -> ImageProfile {
+    let rgba = img.to_rgba8();
+    let (width, height) = rgba.dimensions();
+    let step = ((width as f32 * height as f32 / MAX_SAMPLES as f32)
+        .sqrt()
+        .ceil() as u32)
+        .max(1);
+
+    let mut colors = HashSet::new();
+    let (mut samples, mut flat) = (0u32, 0u32);
+    for y in (0..height).step_by(step as usize) {
+        for x in (0..width).step_by(step as usize) {
+            let pixel = rgba.get_pixel(x, y);
+            if colors.len() <= PALETTE_COLORS {
+                colors.insert(pixel.0);
+            }
+            if x + 1 < width {
+                samples += 1;
+                if rgba.get_pixel(x + 1, y) == pixel {
+                    flat += 1;
+                }
+            }
+        }
+    }
+
+    let color_count = colors.len();
+    let flat_ratio = if samples > 0 {
+        flat as f32 / samples as f32
+    } else {
+        1.0
+    };
+
+    ImageProfile {
+        has_alpha: has_transparency(img),
+        color_count,
+        photographic: color_count > PALETTE_COLORS && flat_ratio < FLAT_RATIO,
+    }
+}
+
+/// Formats worth trying for an image, most likely winner first, with a
+/// short description of why they were picked
+pub fn candidates(profile: &ImageProfile) -> (Vec<OutputFormat>, &'static str) {
+    match (profile.photographic, profile.has_alpha) {
+        (true, false) => (
+            vec![OutputFormat::Jpeg, OutputFormat::WebP],
+            "photo without transparency",
+        ),
+        (true, true) => (
+            vec![OutputFormat::WebP, OutputFormat::Png],
+            "photo with transparency",
+        ),
+        (false, false) => (vec![OutputFormat::Png, OutputFormat::WebP], "flat graphic"),
+        (false, true) => (
+            vec![OutputFormat::Png, OutputFormat::WebP],
+            "flat graphic with transparency",
+        ),
+    }
+}
+
+/// Formats that can store an animation
+pub fn animation_candidates() -> (Vec<OutputFormat>, &'static str) {
+    (
+        vec![OutputFormat::WebP, OutputFormat::Png, OutputFormat::Gif],
+        "animation",
+    )
+}
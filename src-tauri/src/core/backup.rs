@@ -0,0 +1,353 @@
+use crate::core::config::{OutputFormat, ProcessConfig, UndoSummary};
+use crate::core::format::{canonical_extension, detect_format, extension_matches};
+use crate::error::{AppError, AppResult, ResultExt};
+use crate::utils::{get_file_extension, write_atomic};
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const MANIFEST_NAME: &str = "manifest.jsonl";
+
+// Originals are kept under this folder of a run, mirroring their full path
+const FILES_DIR: &str = "files";
+
+// Batch workers append to the same manifest
+static MANIFEST_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ManifestEntry {
+    original: PathBuf,
+    /// Relative to the run folder
+    backup: PathBuf,
+    /// blake3 of the optimized file, so undo can tell if it was edited since
+    optimized_hash: String,
+}
+
+/// One in-place run: a folder named after its start time (UTC) holding the
+/// originals it replaced, plus a manifest of where each one came from.
+///
+/// The manifest is JSON Lines, and each entry is appended before its
+/// original is moved, so an interrupted run can still be undone.
+pub struct BackupRun {
+    dir: PathBuf,
+}
+
+impl BackupRun {
+    /// Starts a new run in a timestamped folder under `root`
+    pub fn create(root: &Path) -> AppResult<Self> {
+        fs::create_dir_all(root).with_path(root)?;
+
+        let stamp = utc_timestamp(SystemTime::now());
+        for attempt in 1..100 {
+            let dir = match attempt {
+                1 => root.join(&stamp),
+                n => root.join(format!("{stamp}-{n}")),
+            };
+            match fs::create_dir(&dir) {
+                Ok(()) => {
+                    let manifest = dir.join(MANIFEST_NAME);
+                    File::create(&manifest).with_path(&manifest)?;
+                    return Ok(Self { dir });
+                }
+                Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(AppError::from(e).with_path(&dir)),
+            }
+        }
+
+        Err(
+            AppError::InvalidConfig("Too many backup runs started at once".to_string())
+                .with_path(root),
+        )
+    }
+
+    /// Opens the folder of an earlier run
+    pub fn open(dir: &Path) -> AppResult<Self> {
+        if !dir.join(MANIFEST_NAME).is_file() {
+            return Err(AppError::InvalidConfig(
+                "Not a backup folder, it has no manifest".to_string(),
+            )
+            .with_path(dir));
+        }
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Moves `original` into the run and writes `data` in its place. If the
+    /// write fails the original is moved back.
+    pub fn replace(&self, original: &Path, data: &[u8]) -> AppResult<()> {
+        let original = std::path::absolute(original).with_path(original)?;
+        let relative = backup_path(&original);
+        let backup = self.dir.join(&relative);
+        if backup.exists() {
+            return Err(AppError::InvalidConfig(
+                "File was already replaced in this run".to_string(),
+            )
+            .with_path(&original));
+        }
+
+        self.append(&ManifestEntry {
+            original: original.clone(),
+            backup: relative,
+            optimized_hash: blake3::hash(data).to_hex().to_string(),
+        })?;
+
+        if let Some(parent) = backup.parent() {
+            fs::create_dir_all(parent).with_path(parent)?;
+        }
+        move_file(&original, &backup)?;
+
+        if let Err(e) = write_atomic(&original, data) {
+            let _ = move_file(&backup, &original);
+            return Err(e);
+        }
+
+        // The new file was created from scratch; keep the original's mode
+        if let Ok(metadata) = fs::metadata(&backup) {
+            let _ = fs::set_permissions(&original, metadata.permissions());
+        }
+
+        Ok(())
+    }
+
+    /// Moves every original recorded in the manifest back into place.
+    ///
+    /// Files edited since the run keep their current contents and are
+    /// reported instead; their entries stay in the manifest so the undo can
+    /// be retried. Once everything is restored the run folder is removed.
+    pub fn undo(&self) -> AppResult<UndoSummary> {
+        let _lock = MANIFEST_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let manifest = self.dir.join(MANIFEST_NAME);
+        let text = fs::read_to_string(&manifest).with_path(&manifest)?;
+
+        let mut summary = UndoSummary::default();
+        let mut remaining = Vec::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let entry: ManifestEntry = serde_json::from_str(line).map_err(|e| {
+                AppError::InvalidConfig(format!("Corrupt backup manifest: {}", e))
+                    .with_path(&manifest)
+            })?;
+            match self.restore(&entry) {
+                Ok(true) => summary.restored += 1,
+                Ok(false) => {}
+                Err(e) => {
+                    summary.errors.push(e.to_info());
+                    remaining.push(line);
+                }
+            }
+        }
+
+        if remaining.is_empty() {
+            fs::remove_file(&manifest).with_path(&manifest)?;
+            remove_empty_dirs(&self.dir);
+        } else {
+            let text = remaining.join("\n") + "\n";
+            write_atomic(&manifest, text.as_bytes())?;
+        }
+
+        Ok(summary)
+    }
+
+    // Returns false for entries whose original was never moved
+    fn restore(&self, entry: &ManifestEntry) -> AppResult<bool> {
+        let original = &entry.original;
+        let backup = self.dir.join(&entry.backup);
+
+        if !backup.exists() {
+            // The run failed before moving it, so the original is untouched
+            if original.exists() {
+                return Ok(false);
+            }
+            return Err(AppError::Io(std::io::Error::new(
+                ErrorKind::NotFound,
+                "the backup of this file is missing",
+            ))
+            .with_path(original));
+        }
+
+        match File::open(original) {
+            Ok(file) => {
+                let mut hash = blake3::Hasher::new();
+                hash.update_reader(file).with_path(original)?;
+                if hash.finalize().to_hex().as_str() != entry.optimized_hash {
+                    return Err(AppError::Processing(
+                        "File changed after it was optimized, keeping the current version"
+                            .to_string(),
+                    )
+                    .with_path(original));
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::NotFound => {}
+            Err(e) => return Err(AppError::from(e).with_path(original)),
+        }
+
+        if let Some(parent) = original.parent() {
+            fs::create_dir_all(parent).with_path(parent)?;
+        }
+        move_file(&backup, original)?;
+        Ok(true)
+    }
+
+    fn append(&self, entry: &ManifestEntry) -> AppResult<()> {
+        let mut line = serde_json::to_vec(entry)
+            .map_err(|e| AppError::Processing(format!("Failed to write manifest: {}", e)))?;
+        line.push(b'\n');
+
+        let manifest = self.dir.join(MANIFEST_NAME);
+        let _lock = MANIFEST_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        let mut file = File::options()
+            .append(true)
+            .open(&manifest)
+            .with_path(&manifest)?;
+        file.write_all(&line).with_path(&manifest)?;
+        file.sync_data().with_path(&manifest)
+    }
+}
+
+/// Config for replacing `path` in place: the output keeps the input's name
+/// and is encoded in the format its contents are in. Files whose extension
+/// names another format are refused, since rewriting them would keep the
+/// misleading name.
+pub fn in_place_config(path: &Path, config: &ProcessConfig) -> AppResult<ProcessConfig> {
+    let (format, output_format) = match detect_format(path)? {
+        Some(ImageFormat::Png) => (ImageFormat::Png, OutputFormat::Png),
+        Some(ImageFormat::Jpeg) => (ImageFormat::Jpeg, OutputFormat::Jpeg),
+        Some(ImageFormat::WebP) => (ImageFormat::WebP, OutputFormat::WebP),
+        Some(ImageFormat::Gif) => (ImageFormat::Gif, OutputFormat::Gif),
+        _ => {
+            return Err(AppError::UnsupportedFormat(
+                "In-place mode only rewrites PNG, JPEG, WebP and GIF files".to_string(),
+            )
+            .with_path(path))
+        }
+    };
+
+    if !extension_matches(get_file_extension(path).as_deref(), format) {
+        return Err(AppError::UnsupportedFormat(format!(
+            "File holds {:?} data under another extension; rename it to .{} first",
+            format,
+            canonical_extension(format)
+        ))
+        .with_path(path));
+    }
+
+    let mut file_config = config.clone();
+    file_config.output_format = output_format;
+    file_config.output_path = path.to_path_buf();
+    Ok(file_config)
+}
+
+// Mirrors the full path, so equal names in different folders don't collide
+fn backup_path(original: &Path) -> PathBuf {
+    let mut path = PathBuf::from(FILES_DIR);
+    for component in original.components() {
+        match component {
+            // Drive letters and UNC shares, minus the characters that can't
+            // appear in a folder name
+            Component::Prefix(prefix) => path.push(
+                prefix
+                    .as_os_str()
+                    .to_string_lossy()
+                    .replace([':', '\\', '?'], ""),
+            ),
+            Component::Normal(part) => path.push(part),
+            _ => {}
+        }
+    }
+    path
+}
+
+// Renames, falling back to copy and delete across file systems
+fn move_file(from: &Path, to: &Path) -> AppResult<()> {
+    match fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == ErrorKind::CrossesDevices => {
+            let data = fs::read(from).with_path(from)?;
+            write_atomic(to, &data)?;
+            fs::remove_file(from).with_path(from)
+        }
+        Err(e) => Err(AppError::from(e).with_path(from)),
+    }
+}
+
+// Deletes `dir` and the folders below it that are empty; anything else the
+// user put there stays
+fn remove_empty_dirs(dir: &Path) {
+    if let Ok(entries) = fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            if entry.file_type().is_ok_and(|kind| kind.is_dir()) {
+                remove_empty_dirs(&entry.path());
+            }
+        }
+    }
+    let _ = fs::remove_dir(dir);
+}
+
+// Sortable and valid in file names everywhere, e.g. 2026-10-19_14-03-27
+fn utc_timestamp(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    let (days, time_of_day) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}_{:02}-{:02}-{:02}",
+        time_of_day / 3_600,
+        time_of_day % 3_600 / 60,
+        time_of_day % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(secs: u64) -> String {
+        utc_timestamp(UNIX_EPOCH + Duration::from_secs(secs))
+    }
+
+    #[test]
+    fn formats_the_epoch() {
+        assert_eq!(at(0), "1970-01-01_00-00-00");
+    }
+
+    #[test]
+    fn handles_leap_days_and_century_rules() {
+        // 2000 is a leap year, 2100 isn't
+        assert_eq!(at(951_782_400), "2000-02-29_00-00-00");
+        assert_eq!(at(4_107_542_399), "2100-02-28_23-59-59");
+        assert_eq!(at(4_107_542_400), "2100-03-01_00-00-00");
+    }
+
+    #[test]
+    fn formats_the_time_of_day() {
+        assert_eq!(at(1_792_418_607), "2026-10-19_14-03-27");
+        assert_eq!(at(1_798_761_599), "2026-12-31_23-59-59");
+    }
+
+    #[test]
+    fn times_before_the_epoch_clamp_to_it() {
+        let before = UNIX_EPOCH - Duration::from_secs(60);
+        assert_eq!(utc_timestamp(before), "1970-01-01_00-00-00");
+    }
+}
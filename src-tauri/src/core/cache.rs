@@ -0,0 +1,209 @@
+use crate::core::config::{
+    DecodeLimits, FileConflictMode, FormatChoice, ProcessConfig, ProcessResult,
+};
+use crate::core::pipeline::compression_ratio;
+use crate::error::{AppResult, ResultExt};
+use crate::utils::write_atomic;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Number of entries the cache keeps before the least recently used ones
+/// are evicted. Entries are a few hundred bytes each.
+pub const MAX_CACHE_ENTRIES: usize = 100_000;
+
+// Eviction goes a little below the limit so it doesn't run on every record
+const EVICT_TARGET: f64 = 0.9;
+
+// Eviction lists the whole cache, so new entries only trigger it this often
+const EVICT_INTERVAL: u64 = 256;
+
+const ENTRY_EXTENSION: &str = "json";
+
+// Entries recorded since the last eviction, across all cache handles
+static RECORDS_SINCE_EVICT: AtomicU64 = AtomicU64::new(0);
+
+/// Identifies one (input content, effective config) combination.
+#[derive(Debug, Clone)]
+pub struct CacheKey(String);
+
+impl CacheKey {
+    /// Hashes the input file contents together with the settings that
+    /// affect the produced output and the input location.
+    pub fn new(input_path: &Path, config: &ProcessConfig) -> AppResult<Self> {
+        let mut content = blake3::Hasher::new();
+        let file = File::open(input_path).with_path(input_path)?;
+        content.update_reader(file).with_path(input_path)?;
+
+        let mut key = blake3::Hasher::new();
+        key.update(content.finalize().as_bytes());
+        key.update(&config_fingerprint(config)?);
+        key.update(input_path.to_string_lossy().as_bytes());
+
+        Ok(Self(key.finalize().to_hex().to_string()))
+    }
+}
+
+// Serialized config without the fields that don't change the output bytes
+fn config_fingerprint(config: &ProcessConfig) -> AppResult<Vec<u8>> {
+    let mut effective = config.clone();
+    effective.file_conflict_mode = FileConflictMode::Overwrite;
+    effective.incremental = false;
+    effective.limits = DecodeLimits::default();
+
+    serde_json::to_vec(&effective)
+        .map_err(|e| crate::error::AppError::Processing(format!("Failed to hash config: {}", e)))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CacheEntry {
+    output_path: PathBuf,
+    original_size: u64,
+    output_size: u64,
+    output_modified: u64,
+    #[serde(default)]
+    warnings: Vec<String>,
+    #[serde(default)]
+    format_choice: Option<FormatChoice>,
+}
+
+/// Records produced outputs so unchanged inputs can be skipped on re-runs.
+///
+/// Each entry is stored as its own small file, so lookups stay O(1) for large
+/// folders and parallel workers never contend on a shared index. The
+/// file's modification time doubles as its last-used time for eviction.
+pub struct ProcessCache {
+    dir: PathBuf,
+}
+
+impl ProcessCache {
+    pub fn open(dir: PathBuf) -> AppResult<Self> {
+        fs::create_dir_all(&dir).with_path(&dir)?;
+        Ok(Self { dir })
+    }
+
+    /// Returns the recorded result if its output still exists and is intact.
+    pub fn lookup(&self, key: &CacheKey) -> Option<ProcessResult> {
+        let entry_path = self.entry_path(key);
+        let data = fs::read(&entry_path).ok()?;
+        let entry: CacheEntry = serde_json::from_slice(&data).ok()?;
+
+        let metadata = fs::metadata(&entry.output_path).ok()?;
+        if metadata.len() != entry.output_size || modified_secs(&metadata)? != entry.output_modified
+        {
+            return None;
+        }
+
+        // Mark as recently used; failing to only affects eviction order
+        if let Ok(file) = File::options().write(true).open(&entry_path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+
+        Some(ProcessResult {
+            success: true,
+            original_size: entry.original_size,
+            new_size: entry.output_size,
+            compression_ratio: compression_ratio(entry.original_size, entry.output_size),
+            output_path: entry.output_path.to_string_lossy().to_string(),
+            error: None,
+            cached: true,
+            unchanged: false,
+            warnings: entry.warnings,
+            format_choice: entry.format_choice,
+        })
+    }
+
+    /// Stores a successful result. Every `EVICT_INTERVAL` records also evict
+    /// old entries.
+    pub fn record(&self, key: &CacheKey, result: &ProcessResult) -> AppResult<()> {
+        if !result.success {
+            return Ok(());
+        }
+
+        let output_path = PathBuf::from(&result.output_path);
+        let metadata = fs::metadata(&output_path).with_path(&output_path)?;
+
+        let entry = CacheEntry {
+            output_path,
+            original_size: result.original_size,
+            output_size: metadata.len(),
+            output_modified: modified_secs(&metadata).unwrap_or_default(),
+            warnings: result.warnings.clone(),
+            format_choice: result.format_choice.clone(),
+        };
+
+        let data = serde_json::to_vec(&entry)
+            .map_err(|e| crate::error::AppError::Processing(e.to_string()))?;
+        // Parallel workers may record the same input at once
+        write_atomic(&self.entry_path(key), &data)?;
+
+        if RECORDS_SINCE_EVICT.fetch_add(1, Ordering::Relaxed) + 1 >= EVICT_INTERVAL {
+            let _ = self.evict();
+        }
+
+        Ok(())
+    }
+
+    /// Removes the least recently used entries until the cache fits its limit
+    pub fn evict(&self) -> AppResult<()> {
+        RECORDS_SINCE_EVICT.store(0, Ordering::Relaxed);
+        let mut entries = self.entries()?;
+        if entries.len() <= MAX_CACHE_ENTRIES {
+            return Ok(());
+        }
+
+        let target = (MAX_CACHE_ENTRIES as f64 * EVICT_TARGET) as usize;
+        entries.sort_by_key(|(_, _, used)| *used);
+        let excess = entries.len() - target;
+        for (path, _, _) in entries.into_iter().take(excess) {
+            let _ = fs::remove_file(&path);
+        }
+        Ok(())
+    }
+
+    /// Deletes every entry and returns the number of bytes freed. Outputs
+    /// are kept; the next run just processes every file again.
+    pub fn clear(&self) -> AppResult<u64> {
+        let mut freed = 0;
+        for (path, size, _) in self.entries()? {
+            if fs::remove_file(&path).is_ok() {
+                freed += size;
+            }
+        }
+        Ok(freed)
+    }
+
+    fn entries(&self) -> AppResult<Vec<(PathBuf, u64, SystemTime)>> {
+        let entries = fs::read_dir(&self.dir)
+            .with_path(&self.dir)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                // Skip temp files of writes still in progress
+                let path = entry.path();
+                if path.extension()? != ENTRY_EXTENSION {
+                    return None;
+                }
+                let metadata = entry.metadata().ok()?;
+                let used = metadata.modified().unwrap_or(UNIX_EPOCH);
+                metadata.is_file().then_some((path, metadata.len(), used))
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    fn entry_path(&self, key: &CacheKey) -> PathBuf {
+        self.dir.join(format!("{}.{ENTRY_EXTENSION}", key.0))
+    }
+}
+
+fn modified_secs(metadata: &fs::Metadata) -> Option<u64> {
+    metadata
+        .modified()
+        .ok()?
+        .duration_since(UNIX_EPOCH)
+        .ok()
+        .map(|d| d.as_secs())
+}
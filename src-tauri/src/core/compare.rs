@@ -0,0 +1,148 @@
+use crate::core::config::{ChannelError, ErrorRegion, ImageComparison};
+use crate::error::AppResult;
+use base64::Engine;
+use image::{imageops, DynamicImage, RgbImage};
+
+// Regions are ranked on a grid of squares this size. A multiple of the
+// 16px JPEG macroblock, so block artifacts line up with cells.
+const REGION_SIZE: u32 = 32;
+
+// How many of the worst regions are reported
+const MAX_REGIONS: usize = 8;
+
+// Inferno-like ramp from no error (black) to the largest error (pale yellow)
+const HEAT_STOPS: [[f32; 3]; 5] = [
+    [0.0, 0.0, 0.0],
+    [60.0, 15.0, 110.0],
+    [190.0, 40.0, 80.0],
+    [250.0, 140.0, 20.0],
+    [255.0, 250.0, 170.0],
+];
+
+/// Compares a processed image with its original channel by channel. The
+/// original is scaled to the processed size when they differ. `amplify`
+/// scales errors before they are colored, so small differences still show
+/// up in the heatmap, which is returned as a PNG no larger than `max_size`.
+pub fn compare_images(
+    original: &DynamicImage,
+    processed: &DynamicImage,
+    amplify: f32,
+    max_size: u32,
+) -> AppResult<ImageComparison> {
+    let processed = processed.to_rgba8();
+    let (width, height) = processed.dimensions();
+
+    let resized = (original.width(), original.height()) != (width, height);
+    let original = if resized {
+        imageops::resize(
+            &original.to_rgba8(),
+            width,
+            height,
+            imageops::FilterType::Lanczos3,
+        )
+    } else {
+        original.to_rgba8()
+    };
+
+    // Largest channel difference per pixel drives the heatmap and regions
+    let mut errors = vec![0u8; (width * height) as usize];
+    let mut sums = [0u64; 4];
+    let mut squares = [0u64; 4];
+    let mut maxima = [0u8; 4];
+
+    for ((a, b), error) in original
+        .pixels()
+        .zip(processed.pixels())
+        .zip(errors.iter_mut())
+    {
+        for c in 0..4 {
+            let diff = a[c].abs_diff(b[c]);
+            sums[c] += u64::from(diff);
+            squares[c] += u64::from(diff) * u64::from(diff);
+            maxima[c] = maxima[c].max(diff);
+            *error = (*error).max(diff);
+        }
+    }
+
+    let pixels = f64::from(width) * f64::from(height);
+    let channel = |c: usize| ChannelError {
+        mean: sums[c] as f64 / pixels.max(1.0),
+        max: maxima[c],
+        rmse: (squares[c] as f64 / pixels.max(1.0)).sqrt(),
+    };
+
+    // PSNR over the color channels; identical images have none
+    let mse = squares[..3].iter().sum::<u64>() as f64 / (pixels * 3.0).max(1.0);
+    let psnr = (mse > 0.0).then(|| 10.0 * (255.0 * 255.0 / mse).log10());
+
+    let mut heatmap = DynamicImage::ImageRgb8(heatmap(&errors, width, height, amplify));
+    if width.max(height) > max_size {
+        heatmap = heatmap.resize(max_size, max_size, imageops::FilterType::Triangle);
+    }
+    let mut png_data = Vec::new();
+    heatmap.write_to(
+        &mut std::io::Cursor::new(&mut png_data),
+        image::ImageFormat::Png,
+    )?;
+
+    Ok(ImageComparison {
+        heatmap: base64::engine::general_purpose::STANDARD.encode(png_data),
+        width,
+        height,
+        resized,
+        red: channel(0),
+        green: channel(1),
+        blue: channel(2),
+        alpha: channel(3),
+        psnr,
+        regions: worst_regions(&errors, width, height),
+    })
+}
+
+fn heatmap(errors: &[u8], width: u32, height: u32, amplify: f32) -> RgbImage {
+    let gain = amplify.max(1.0) / 255.0;
+    RgbImage::from_fn(width, height, |x, y| {
+        let error = f32::from(errors[(y * width + x) as usize]);
+        image::Rgb(heat_color((error * gain).min(1.0)))
+    })
+}
+
+fn heat_color(t: f32) -> [u8; 3] {
+    let scaled = t * (HEAT_STOPS.len() - 1) as f32;
+    let index = (scaled.floor() as usize).min(HEAT_STOPS.len() - 2);
+    let frac = scaled - index as f32;
+    let (from, to) = (HEAT_STOPS[index], HEAT_STOPS[index + 1]);
+    [0, 1, 2].map(|c| (from[c] + (to[c] - from[c]) * frac).round() as u8)
+}
+
+// Grid cells ranked by mean error, worst first, skipping error-free cells
+fn worst_regions(errors: &[u8], width: u32, height: u32) -> Vec<ErrorRegion> {
+    let mut regions = Vec::new();
+    for y in (0..height).step_by(REGION_SIZE as usize) {
+        for x in (0..width).step_by(REGION_SIZE as usize) {
+            let (w, h) = (REGION_SIZE.min(width - x), REGION_SIZE.min(height - y));
+            let (mut sum, mut max) = (0u64, 0u8);
+            for row in y..y + h {
+                let start = (row * width + x) as usize;
+                for &error in &errors[start..start + w as usize] {
+                    sum += u64::from(error);
+                    max = max.max(error);
+                }
+            }
+            if max > 0 {
+                regions.push(ErrorRegion {
+                    x,
+                    y,
+                    width: w,
+                    height: h,
+                    mean_error: sum as f64 / f64::from(w * h),
+                    max_error: max,
+                });
+            }
+        }
+    }
+
+    regions.sort_by(|a, b| b.mean_error.total_cmp(&a.mean_error));
+    regions.truncate(MAX_REGIONS);
+    regions
+}
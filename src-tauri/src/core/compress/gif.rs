@@ -0,0 +1,339 @@
+use crate::core::animation::Animation;
+use crate::core::compress::quantize::{as_rgba_pixels, quantize_shared_palette};
+use crate::core::config::GifOptions;
+use crate::error::{AppError, AppResult};
+use gif::{DisposalMethod, Frame, Repeat};
+use image::{DynamicImage, RgbaImage};
+use imagequant::{Attributes, RGBA};
+use std::borrow::Cow;
+
+// GIF transparency is binary: anything below this alpha becomes transparent
+const ALPHA_THRESHOLD: u8 = 128;
+
+struct QuantizedFrame {
+    palette: Vec<RGBA>,
+    indices: Vec<u8>,
+}
+
+struct PlannedFrame {
+    /// Index into the source frames
+    source: usize,
+    /// Centiseconds
+    delay: u16,
+    /// Changed region (x, y, width, height); `None` covers the canvas
+    rect: Option<(u32, u32, u32, u32)>,
+    dispose: DisposalMethod,
+}
+
+impl PlannedFrame {
+    fn full(source: usize, delay: u16) -> Self {
+        Self {
+            source,
+            delay,
+            rect: None,
+            dispose: DisposalMethod::Keep,
+        }
+    }
+}
+
+pub fn compress_gif(img: &DynamicImage, options: &GifOptions) -> AppResult<Vec<u8>> {
+    let frame = binarize_alpha(img.to_rgba8(), options.preserve_transparency);
+    encode_frames(vec![frame], &[0], 0, options)
+}
+
+/// Encodes frames with imagequant palettes. With `optimize_frames` each frame
+/// only stores the rectangle that changed since the previous one.
+pub fn compress_gif_animated(animation: &Animation, options: &GifOptions) -> AppResult<Vec<u8>> {
+    let frames: Vec<RgbaImage> = animation
+        .frames
+        .iter()
+        .map(|frame| binarize_alpha(frame.image.to_rgba8(), options.preserve_transparency))
+        .collect();
+    let delays: Vec<u32> = animation.frames.iter().map(|f| f.delay_ms).collect();
+
+    encode_frames(frames, &delays, animation.loop_count, options)
+}
+
+// `delays_ms` has one entry per frame; a loop count of 0 plays forever
+fn encode_frames(
+    frames: Vec<RgbaImage>,
+    delays_ms: &[u32],
+    loop_count: u32,
+    options: &GifOptions,
+) -> AppResult<Vec<u8>> {
+    let (width, height) = frames.first().map_or((0, 0), |f| f.dimensions());
+    let (Ok(gif_width), Ok(gif_height)) = (u16::try_from(width), u16::try_from(height)) else {
+        return Err(AppError::InvalidConfig(format!(
+            "GIF dimensions are limited to 65535px, got {}x{}",
+            width, height
+        )));
+    };
+
+    let mut liq = Attributes::new();
+    liq.set_quality(0, options.quality)?;
+
+    let quantized: Vec<QuantizedFrame> = if options.shared_palette {
+        let (palette, indexed) = quantize_shared_palette(&liq, &frames, options.dithering)?;
+        indexed
+            .into_iter()
+            .map(|indices| QuantizedFrame {
+                palette: palette.clone(),
+                indices,
+            })
+            .collect()
+    } else {
+        frames
+            .iter()
+            .map(|frame| quantize_frame(&liq, frame, options.dithering))
+            .collect::<AppResult<Vec<_>>>()?
+    };
+
+    let plan = plan_frames(&frames, delays_ms, options.optimize_frames);
+
+    let gif_frames: Vec<Frame<'static>> = plan
+        .iter()
+        .map(|planned| build_frame(&quantized[planned.source], width, planned))
+        .collect();
+
+    let global_palette = if options.shared_palette {
+        gif_frames
+            .first()
+            .and_then(|f| f.palette.clone())
+            .unwrap_or_default()
+    } else {
+        Vec::new()
+    };
+
+    let mut gif_data = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut gif_data, gif_width, gif_height, &global_palette)
+            .map_err(gif_error)?;
+
+        if frames.len() > 1 {
+            let repeat = match loop_count {
+                0 => Repeat::Infinite,
+                plays => Repeat::Finite((plays - 1).min(u16::MAX as u32) as u16),
+            };
+            encoder.set_repeat(repeat).map_err(gif_error)?;
+        }
+
+        for mut frame in gif_frames {
+            if options.shared_palette {
+                frame.palette = None;
+            }
+            encoder.write_frame(&frame).map_err(gif_error)?;
+        }
+    }
+
+    Ok(gif_data)
+}
+
+fn binarize_alpha(mut rgba: RgbaImage, preserve_transparency: bool) -> RgbaImage {
+    for pixel in rgba.pixels_mut() {
+        pixel[3] = if !preserve_transparency || pixel[3] >= ALPHA_THRESHOLD {
+            255
+        } else {
+            0
+        };
+    }
+    rgba
+}
+
+fn quantize_frame(
+    liq: &Attributes,
+    rgba: &RgbaImage,
+    dithering: bool,
+) -> AppResult<QuantizedFrame> {
+    let mut img = liq.new_image(
+        as_rgba_pixels(rgba),
+        rgba.width() as usize,
+        rgba.height() as usize,
+        0.0,
+    )?;
+    let mut res = liq.quantize(&mut img)?;
+    res.set_dithering_level(if dithering { 1.0 } else { 0.0 })?;
+    let (palette, indices) = res.remapped(&mut img)?;
+
+    Ok(QuantizedFrame { palette, indices })
+}
+
+// Decides which frames to write and which rectangle each one covers
+fn plan_frames(frames: &[RgbaImage], delays_ms: &[u32], optimize: bool) -> Vec<PlannedFrame> {
+    let mut plan: Vec<PlannedFrame> = Vec::with_capacity(frames.len());
+
+    for (index, (rgba, &delay_ms)) in frames.iter().zip(delays_ms).enumerate() {
+        let delay = ((delay_ms + 5) / 10).min(u16::MAX as u32) as u16;
+
+        let Some(last) = plan.last_mut() else {
+            plan.push(PlannedFrame::full(index, delay));
+            continue;
+        };
+        let prev = &frames[last.source];
+
+        // Pixels that turn transparent can't be drawn over the previous
+        // frame, so it has to cover the canvas and clear itself first
+        if reveals_transparency(prev, rgba) {
+            last.rect = None;
+            last.dispose = DisposalMethod::Background;
+            plan.push(PlannedFrame::full(index, delay));
+            continue;
+        }
+
+        if !optimize {
+            plan.push(PlannedFrame::full(index, delay));
+            continue;
+        }
+
+        match changed_rect(prev, rgba) {
+            Some(rect) => plan.push(PlannedFrame {
+                source: index,
+                delay,
+                rect: Some(rect),
+                dispose: DisposalMethod::Keep,
+            }),
+            // Identical frame: extend the previous one instead
+            None => last.delay = last.delay.saturating_add(delay),
+        }
+    }
+
+    plan
+}
+
+// Bounding box (x, y, width, height) of pixels that differ between frames
+fn changed_rect(prev: &RgbaImage, current: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    let (mut min_x, mut min_y) = (u32::MAX, u32::MAX);
+    let (mut max_x, mut max_y) = (0, 0);
+
+    for (x, y, pixel) in current.enumerate_pixels() {
+        if prev.get_pixel(x, y) != pixel {
+            min_x = min_x.min(x);
+            min_y = min_y.min(y);
+            max_x = max_x.max(x);
+            max_y = max_y.max(y);
+        }
+    }
+
+    (min_x != u32::MAX).then(|| (min_x, min_y, max_x - min_x + 1, max_y - min_y + 1))
+}
+
+fn reveals_transparency(prev: &RgbaImage, current: &RgbaImage) -> bool {
+    prev.pixels()
+        .zip(current.pixels())
+        .any(|(p, c)| p[3] != 0 && c[3] == 0)
+}
+
+fn build_frame(
+    frame: &QuantizedFrame,
+    canvas_width: u32,
+    planned: &PlannedFrame,
+) -> Frame<'static> {
+    // Collapse every transparent palette entry onto a single index
+    let transparent = frame.palette.iter().position(|c| c.a < ALPHA_THRESHOLD);
+    let index_at = |i: usize| -> u8 {
+        let index = frame.indices[i];
+        match transparent {
+            Some(t) if frame.palette[index as usize].a < ALPHA_THRESHOLD => t as u8,
+            _ => index,
+        }
+    };
+
+    let canvas_height = frame.indices.len() as u32 / canvas_width.max(1);
+    let (left, top, width, height) = planned.rect.unwrap_or((0, 0, canvas_width, canvas_height));
+    let buffer: Vec<u8> = (top..top + height)
+        .flat_map(|y| {
+            let start = (y * canvas_width + left) as usize;
+            (start..start + width as usize).map(index_at)
+        })
+        .collect();
+
+    Frame {
+        left: left as u16,
+        top: top as u16,
+        width: width as u16,
+        height: height as u16,
+        delay: planned.delay,
+        dispose: planned.dispose,
+        transparent: transparent.map(|t| t as u8),
+        // Dropped again before writing when the palette is global
+        palette: Some(frame.palette.iter().flat_map(|c| [c.r, c.g, c.b]).collect()),
+        buffer: Cow::Owned(buffer),
+        ..Frame::default()
+    }
+}
+
+fn gif_error(err: gif::EncodingError) -> AppError {
+    AppError::Encode(format!("GIF encoding error: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    const RED: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const BLUE: Rgba<u8> = Rgba([0, 0, 255, 255]);
+    const CLEAR: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+    fn filled(color: Rgba<u8>) -> RgbaImage {
+        RgbaImage::from_pixel(16, 12, color)
+    }
+
+    // Source frame, delay, rectangle written and whether it's kept
+    type Summary = (usize, u16, Option<(u32, u32, u32, u32)>, bool);
+
+    fn summary(plan: &[PlannedFrame]) -> Vec<Summary> {
+        plan.iter()
+            .map(|frame| {
+                let keep = frame.dispose == DisposalMethod::Keep;
+                (frame.source, frame.delay, frame.rect, keep)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn identical_frames_extend_the_previous_delay() {
+        let frames = [filled(RED), filled(RED), filled(BLUE)];
+        let plan = plan_frames(&frames, &[40, 65, 100], true);
+        // Delays round to centiseconds: 4 + 7
+        assert_eq!(
+            summary(&plan),
+            [(0, 11, None, true), (2, 10, Some((0, 0, 16, 12)), true)]
+        );
+    }
+
+    #[test]
+    fn only_the_changed_region_is_written() {
+        let mut second = filled(RED);
+        for (x, y) in [(3, 2), (7, 5)] {
+            second.put_pixel(x, y, BLUE);
+        }
+        let plan = plan_frames(&[filled(RED), second], &[100, 100], true);
+        assert_eq!(
+            summary(&plan),
+            [(0, 10, None, true), (1, 10, Some((3, 2, 5, 4)), true)]
+        );
+    }
+
+    #[test]
+    fn unoptimized_frames_cover_the_canvas() {
+        let frames = [filled(RED), filled(RED), filled(BLUE)];
+        let plan = plan_frames(&frames, &[100, 100, 100], false);
+        assert_eq!(
+            summary(&plan),
+            [
+                (0, 10, None, true),
+                (1, 10, None, true),
+                (2, 10, None, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn revealed_transparency_clears_the_previous_frame() {
+        let mut second = filled(RED);
+        second.put_pixel(0, 0, CLEAR);
+        let plan = plan_frames(&[filled(RED), second], &[100, 100], true);
+        assert_eq!(summary(&plan), [(0, 10, None, false), (1, 10, None, true)]);
+        assert_eq!(plan[0].dispose, DisposalMethod::Background);
+    }
+}
@@ -0,0 +1,339 @@
+use crate::core::config::{JpegOptions, JpegTransform, MetadataMode};
+use crate::error::{AppError, AppResult};
+use mozjpeg_sys::*;
+use std::ffi::{c_int, c_ulong, c_void, CStr};
+use std::panic::{self, AssertUnwindSafe};
+use std::{mem, ptr};
+
+// transupp.h's jpeg_transform_info. The binding in mozjpeg-sys predates the
+// drop_* fields, so its layout doesn't match what the C library writes.
+#[repr(C)]
+struct TransformInfo {
+    transform: JXFORM_CODE,
+    perfect: boolean,
+    trim: boolean,
+    force_grayscale: boolean,
+    crop: boolean,
+    slow_hflip: boolean,
+    crop_width: JDIMENSION,
+    crop_width_set: JCROP_CODE,
+    crop_height: JDIMENSION,
+    crop_height_set: JCROP_CODE,
+    crop_xoffset: JDIMENSION,
+    crop_xoffset_set: JCROP_CODE,
+    crop_yoffset: JDIMENSION,
+    crop_yoffset_set: JCROP_CODE,
+    drop_ptr: *mut jpeg_decompress_struct,
+    drop_coef_arrays: *mut jvirt_barray_ptr,
+    num_components: c_int,
+    workspace_coef_arrays: *mut jvirt_barray_ptr,
+    output_width: JDIMENSION,
+    output_height: JDIMENSION,
+    x_crop_offset: JDIMENSION,
+    y_crop_offset: JDIMENSION,
+    drop_width: JDIMENSION,
+    drop_height: JDIMENSION,
+    imcu_sample_width: c_int,
+    imcu_sample_height: c_int,
+}
+
+extern "C-unwind" {
+    fn jtransform_request_workspace(
+        srcinfo: *mut jpeg_decompress_struct,
+        info: *mut TransformInfo,
+    ) -> boolean;
+    fn jtransform_adjust_parameters(
+        srcinfo: *mut jpeg_decompress_struct,
+        dstinfo: *mut jpeg_compress_struct,
+        src_coef_arrays: *mut jvirt_barray_ptr,
+        info: *mut TransformInfo,
+    ) -> *mut jvirt_barray_ptr;
+    fn jtransform_execute_transform(
+        srcinfo: *mut jpeg_decompress_struct,
+        dstinfo: *mut jpeg_compress_struct,
+        src_coef_arrays: *mut jvirt_barray_ptr,
+        info: *mut TransformInfo,
+    );
+    fn jcopy_markers_setup(srcinfo: *mut jpeg_decompress_struct, option: JCOPY_OPTION);
+    fn jcopy_markers_execute(
+        srcinfo: *mut jpeg_decompress_struct,
+        dstinfo: *mut jpeg_compress_struct,
+        option: JCOPY_OPTION,
+    );
+}
+
+extern "C" {
+    // jpeg_mem_dest allocates the output with malloc
+    fn free(ptr: *mut c_void);
+}
+
+const APP1: c_int = 0xE1;
+
+/// Rewrites a JPEG from its DCT coefficients, like jpegtran: Huffman tables
+/// are rebuilt, the scan layout changed and rotations or flips applied
+/// without decoding pixels, so there is no generation loss.
+pub fn optimize_jpeg_lossless(
+    data: &[u8],
+    options: &JpegOptions,
+    metadata: &MetadataMode,
+) -> AppResult<Vec<u8>> {
+    // The EXIF block isn't copied for KeepOrientation, so the tag is written
+    // back on its own. A transform already puts the pixels upright.
+    let orientation = match (metadata, options.transform) {
+        (MetadataMode::KeepOrientation, JpegTransform::None) => read_orientation(data),
+        _ => None,
+    };
+
+    let copy = match metadata {
+        MetadataMode::StripAll | MetadataMode::KeepOrientation => JCOPY_OPTION_JCOPYOPT_NONE,
+        MetadataMode::KeepColorProfile => JCOPY_OPTION_JCOPYOPT_ICC,
+        MetadataMode::Custom => JCOPY_OPTION_JCOPYOPT_ALL,
+    };
+
+    let mut err = error_manager();
+    // SAFETY: see `error_manager`; both structs are destroyed before `err`
+    unsafe {
+        let mut src: jpeg_decompress_struct = mem::zeroed();
+        src.common.err = &mut err;
+        jpeg_create_decompress(&mut src);
+        let mut dst: jpeg_compress_struct = mem::zeroed();
+        dst.common.err = &mut err;
+        jpeg_create_compress(&mut dst);
+
+        let mut out_buffer: *mut u8 = ptr::null_mut();
+        let mut out_size: c_ulong = 0;
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            transcode(
+                &mut src,
+                &mut dst,
+                data,
+                options,
+                copy,
+                orientation,
+                &mut out_buffer,
+                &mut out_size,
+            )
+        }));
+
+        jpeg_destroy_compress(&mut dst);
+        jpeg_destroy_decompress(&mut src);
+
+        let output = match &result {
+            Ok(Ok(())) if !out_buffer.is_null() => {
+                std::slice::from_raw_parts(out_buffer, out_size as usize).to_vec()
+            }
+            _ => Vec::new(),
+        };
+        if !out_buffer.is_null() {
+            free(out_buffer.cast());
+        }
+
+        match result {
+            Ok(Ok(())) => Ok(output),
+            Ok(Err(e)) => Err(e),
+            Err(payload) => Err(AppError::Processing(format!(
+                "Lossless JPEG transform failed: {}",
+                panic_message(&*payload)
+            ))),
+        }
+    }
+}
+
+// Follows jpegtran.c: read coefficients, transform, write them back
+#[allow(clippy::too_many_arguments)]
+unsafe fn transcode(
+    src: &mut jpeg_decompress_struct,
+    dst: &mut jpeg_compress_struct,
+    data: &[u8],
+    options: &JpegOptions,
+    copy: JCOPY_OPTION,
+    orientation: Option<u16>,
+    out_buffer: &mut *mut u8,
+    out_size: &mut c_ulong,
+) -> AppResult<()> {
+    let mut info: TransformInfo = mem::zeroed();
+    info.transform = transform_code(options.transform);
+    // Either refuse partial edge blocks or drop them, jpegtran's -perfect
+    // and -trim
+    info.perfect = boolean::from(!options.trim_edges);
+    info.trim = boolean::from(options.trim_edges);
+
+    jpeg_mem_src(src, data.as_ptr(), data.len() as c_ulong);
+    jcopy_markers_setup(src, copy);
+    jpeg_read_header(src, 1);
+
+    // Fails when edge blocks would have to move and trimming is off
+    if jtransform_request_workspace(src, &mut info) == 0 {
+        return Err(AppError::InvalidConfig(
+            "Image size isn't a multiple of the JPEG block size, so it can't be \
+             rotated or flipped losslessly without trimming the edges"
+                .to_string(),
+        ));
+    }
+
+    let src_coefficients = jpeg_read_coefficients(src);
+    // Checked now because starting compression resets the warning count
+    check_warnings(src)?;
+    jpeg_copy_critical_parameters(src, dst);
+    let dst_coefficients = jtransform_adjust_parameters(src, dst, src_coefficients, &mut info);
+
+    dst.optimize_coding = boolean::from(options.optimize_coding);
+    if options.progressive {
+        jpeg_simple_progression(dst);
+    } else {
+        // jpeg_copy_critical_parameters leaves mozjpeg's progressive default
+        dst.num_scans = 0;
+        dst.scan_info = ptr::null();
+        jpeg_c_set_bool_param(dst, J_BOOLEAN_PARAM::JBOOLEAN_OPTIMIZE_SCANS, 0);
+    }
+
+    jpeg_mem_dest(dst, out_buffer, out_size);
+    jpeg_write_coefficients(dst, dst_coefficients);
+    jcopy_markers_execute(src, dst, copy);
+    if let Some(orientation) = orientation {
+        let exif = orientation_exif(orientation);
+        jpeg_write_marker(dst, APP1, exif.as_ptr(), exif.len() as u32);
+    }
+    jtransform_execute_transform(src, dst, src_coefficients, &mut info);
+
+    jpeg_finish_compress(dst);
+    jpeg_finish_decompress(src);
+    check_warnings(src)
+}
+
+// libjpeg pads truncated or corrupt data with gray and only warns, which
+// would pass the damage off as a lossless rewrite
+unsafe fn check_warnings(src: &jpeg_decompress_struct) -> AppResult<()> {
+    if (*src.common.err).num_warnings > 0 {
+        return Err(AppError::Decode(
+            "JPEG data is damaged, so it can't be rewritten losslessly".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+fn transform_code(transform: JpegTransform) -> JXFORM_CODE {
+    match transform {
+        JpegTransform::None => JXFORM_CODE_JXFORM_NONE,
+        JpegTransform::Rotate90 => JXFORM_CODE_JXFORM_ROT_90,
+        JpegTransform::Rotate180 => JXFORM_CODE_JXFORM_ROT_180,
+        JpegTransform::Rotate270 => JXFORM_CODE_JXFORM_ROT_270,
+        JpegTransform::FlipHorizontal => JXFORM_CODE_JXFORM_FLIP_H,
+        JpegTransform::FlipVertical => JXFORM_CODE_JXFORM_FLIP_V,
+    }
+}
+
+fn read_orientation(data: &[u8]) -> Option<u16> {
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::Cursor::new(data))
+        .ok()?;
+    exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+        .map(|v| v as u16)
+        .filter(|&v| v != 1)
+}
+
+// An APP1 segment holding a single-entry IFD with the Orientation tag
+fn orientation_exif(orientation: u16) -> Vec<u8> {
+    let mut exif = b"Exif\0\0MM\0\x2A\0\0\0\x08".to_vec();
+    exif.extend_from_slice(&1u16.to_be_bytes()); // entry count
+    exif.extend_from_slice(&0x0112u16.to_be_bytes()); // Orientation
+    exif.extend_from_slice(&3u16.to_be_bytes()); // SHORT
+    exif.extend_from_slice(&1u32.to_be_bytes()); // value count
+    exif.extend_from_slice(&orientation.to_be_bytes());
+    exif.extend_from_slice(&[0, 0]); // value padding
+    exif.extend_from_slice(&0u32.to_be_bytes()); // no next IFD
+    exif
+}
+
+/// An error manager for libjpeg calls made from Rust.
+///
+/// libjpeg's default error handler calls exit(). This one unwinds instead,
+/// so every libjpeg call made with it has to run inside `catch_unwind`, and
+/// `panic_message` turns the payload back into libjpeg's message. Warnings
+/// are counted in `num_warnings` without being printed. The compress and
+/// decompress structs keep a pointer to the manager, so it has to stay in
+/// place until they are destroyed.
+pub(crate) fn error_manager() -> jpeg_error_mgr {
+    // SAFETY: jpeg_std_error only fills in the struct it is given
+    unsafe {
+        let mut err: jpeg_error_mgr = mem::zeroed();
+        jpeg_std_error(&mut err);
+        err.error_exit = Some(error_exit);
+        err.emit_message = Some(count_warning);
+        err
+    }
+}
+
+/// The libjpeg message carried by a panic from `error_exit`
+pub(crate) fn panic_message(payload: &(dyn std::any::Any + Send)) -> &str {
+    payload
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .unwrap_or("libjpeg error")
+}
+
+unsafe extern "C-unwind" fn error_exit(cinfo: &mut jpeg_common_struct) {
+    let buffer = [0u8; 80];
+    if let Some(format_message) = (*cinfo.err).format_message {
+        format_message(cinfo, &buffer);
+    }
+    let message = CStr::from_bytes_until_nul(&buffer)
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    panic::resume_unwind(Box::new(message));
+}
+
+// Counts recoverable warnings (e.g. extraneous bytes or truncated data)
+// like libjpeg's default, without printing them to stderr
+unsafe extern "C-unwind" fn count_warning(cinfo: &mut jpeg_common_struct, level: c_int) {
+    if level < 0 {
+        (*cinfo.err).num_warnings += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::codecs::jpeg::JpegEncoder;
+    use image::RgbImage;
+
+    fn sample_jpeg() -> Vec<u8> {
+        // Large enough that cutting it in half lands in the scan data
+        let img = RgbImage::from_fn(512, 384, |x, y| {
+            image::Rgb([x as u8, (x ^ y) as u8, y as u8])
+        });
+        let mut data = Vec::new();
+        JpegEncoder::new_with_quality(&mut data, 85)
+            .encode_image(&img)
+            .unwrap();
+        data
+    }
+
+    fn options() -> JpegOptions {
+        JpegOptions {
+            lossless: true,
+            ..JpegOptions::default()
+        }
+    }
+
+    #[test]
+    fn rewrite_keeps_pixels() {
+        let data = sample_jpeg();
+        let output = optimize_jpeg_lossless(&data, &options(), &MetadataMode::StripAll).unwrap();
+
+        let before = image::load_from_memory(&data).unwrap().to_rgb8();
+        let after = image::load_from_memory(&output).unwrap().to_rgb8();
+        assert_eq!(before, after);
+    }
+
+    #[test]
+    fn truncated_jpeg_is_rejected() {
+        let data = sample_jpeg();
+        let truncated = &data[..data.len() / 2];
+
+        let result = optimize_jpeg_lossless(truncated, &options(), &MetadataMode::StripAll);
+        assert!(matches!(result, Err(AppError::Decode(_))));
+    }
+}
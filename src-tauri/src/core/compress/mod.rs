@@ -1,7 +1,13 @@
+pub mod gif;
 pub mod jpeg;
+pub mod jpeg_lossless;
 pub mod png;
+pub mod png_reduce;
+pub mod quantize;
 pub mod webp;
 
+pub use gif::{compress_gif, compress_gif_animated};
 pub use jpeg::compress_jpeg;
-pub use png::compress_png;
-pub use webp::compress_webp;
+pub use jpeg_lossless::optimize_jpeg_lossless;
+pub use png::{compress_apng, compress_png};
+pub use webp::{compress_webp, compress_webp_animated, encode_webp_lossy};
@@ -1,7 +1,12 @@
+use crate::core::animation::Animation;
+use crate::core::compress::png_reduce::{
+    pack_indices, palette_bit_depth, reduce_color_type, ReducedImage,
+};
+use crate::core::compress::quantize::{as_rgba_pixels, quantize_shared_palette};
 use crate::core::config::PngOptions;
 use crate::error::{AppError, AppResult};
 use image::DynamicImage;
-use imagequant::Attributes;
+use imagequant::{Attributes, RGBA};
 
 pub fn compress_png(img: &DynamicImage, options: &PngOptions) -> AppResult<Vec<u8>> {
     if !options.lossy {
@@ -14,54 +19,40 @@ pub fn compress_png(img: &DynamicImage, options: &PngOptions) -> AppResult<Vec<u
     let width = rgba.width();
     let height = rgba.height();
 
-    let mut liq = Attributes::new();
-
-    // Map quality (0-100) to color count (2-256)
-    let max_colors = ((options.quality as f32 / 100.0) * 254.0 + 2.0) as u32;
-    liq.set_max_colors(max_colors)?;
+    let liq = quantizer(options)?;
+    let mut img_liq = liq.new_image(as_rgba_pixels(&rgba), width as usize, height as usize, 0.0)?;
 
-    if options.dithering {
-        liq.set_quality(0, options.quality)?;
-    } else {
-        liq.set_quality(0, options.quality)?;
-    }
-
-    // Convert image data to RGBA format for imagequant
-    // imagequant expects &[RGBA<u8>], not &[u8]
-    let img_data: &[imagequant::RGBA] = unsafe {
-        std::slice::from_raw_parts(
-            rgba.as_raw().as_ptr() as *const imagequant::RGBA,
-            (width * height) as usize,
-        )
+    let mut res = match liq.quantize(&mut img_liq) {
+        Ok(res) => res,
+        // The palette can't reach min_quality, so keep every color instead
+        Err(imagequant::Error::QualityTooLow) => return compress_png_lossless(img),
+        Err(e) => return Err(e.into()),
     };
-
-    let mut img_liq = liq.new_image(img_data, width as usize, height as usize, 0.0)?;
-
-    let mut res = liq.quantize(&mut img_liq)?;
-
-    if !options.dithering {
-        res.set_dithering_level(0.0)?;
-    }
+    res.set_dithering_level(if options.dithering { 1.0 } else { 0.0 })?;
 
     let (palette, pixels) = res.remapped(&mut img_liq)?;
 
+    // Few colors fit in fewer bits per pixel
+    let bit_depth = palette_bit_depth(palette.len());
+    let pixels = pack_indices(&pixels, width as usize, bit_depth);
+
     // Create PNG from quantized data
     let mut png_data = Vec::new();
     {
         let mut encoder = png::Encoder::new(&mut png_data, width, height);
         encoder.set_color(png::ColorType::Indexed);
-        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_depth(bit_depth);
+        encoder.set_compression(png::Compression::Best);
 
         // Set palette
         let palette_rgb: Vec<u8> = palette
             .iter()
-            .flat_map(|rgba| vec![rgba.r, rgba.g, rgba.b])
+            .flat_map(|rgba| [rgba.r, rgba.g, rgba.b])
             .collect();
         encoder.set_palette(palette_rgb);
 
         if options.preserve_transparency {
-            let alpha: Vec<u8> = palette.iter().map(|rgba| rgba.a).collect();
-            encoder.set_trns(alpha);
+            encoder.set_trns(palette_alpha(&palette));
         }
 
         let mut writer = encoder.write_header()?;
@@ -71,20 +62,122 @@ pub fn compress_png(img: &DynamicImage, options: &PngOptions) -> AppResult<Vec<u
     Ok(png_data)
 }
 
+// imagequant settings shared by still and animated PNGs
+fn quantizer(options: &PngOptions) -> AppResult<Attributes> {
+    let mut liq = Attributes::new();
+    let target = options.quality.min(100);
+    liq.set_quality(options.min_quality.min(target), target)?;
+    liq.set_speed(i32::from(options.speed.clamp(1, 10)))?;
+    liq.set_min_posterization(options.posterize.min(4))?;
+    Ok(liq)
+}
+
+// tRNS entries for the palette, leaving out trailing opaque colors since
+// PNG treats missing entries as fully opaque
+fn palette_alpha(palette: &[RGBA]) -> Vec<u8> {
+    let len = palette
+        .iter()
+        .rposition(|rgba| rgba.a < 255)
+        .map_or(0, |last| last + 1);
+    palette[..len].iter().map(|rgba| rgba.a).collect()
+}
+
 fn compress_png_lossless(img: &DynamicImage) -> AppResult<Vec<u8>> {
-    let mut png_data = Vec::new();
     let rgba = img.to_rgba8();
-    let width = rgba.width();
-    let height = rgba.height();
+    let reduced = reduce_color_type(&rgba);
+    let png_data = write_reduced(&reduced, rgba.width(), rgba.height())?;
+
+    // Decode the result as a safety net: a reduction that changed any pixel
+    // falls back to plain RGBA
+    let exact = image::load_from_memory_with_format(&png_data, image::ImageFormat::Png)
+        .is_ok_and(|decoded| decoded.to_rgba8() == rgba);
+    if exact {
+        return Ok(png_data);
+    }
 
+    let rgba_only = ReducedImage {
+        color_type: png::ColorType::Rgba,
+        bit_depth: png::BitDepth::Eight,
+        data: rgba.as_raw().clone(),
+        palette: None,
+        trns: None,
+    };
+    write_reduced(&rgba_only, rgba.width(), rgba.height())
+}
+
+fn write_reduced(reduced: &ReducedImage, width: u32, height: u32) -> AppResult<Vec<u8>> {
+    let mut png_data = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut png_data, width, height);
+        encoder.set_color(reduced.color_type);
+        encoder.set_depth(reduced.bit_depth);
+        encoder.set_compression(png::Compression::Best);
+        if let Some(palette) = &reduced.palette {
+            encoder.set_palette(palette.as_slice());
+        }
+        if let Some(trns) = &reduced.trns {
+            encoder.set_trns(trns.as_slice());
+        }
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&reduced.data)?;
+    }
+
+    Ok(png_data)
+}
+
+/// Encodes an animation as APNG. Lossy mode quantizes all frames to one
+/// shared palette, since APNG frames share a single PLTE chunk.
+pub fn compress_apng(animation: &Animation, options: &PngOptions) -> AppResult<Vec<u8>> {
+    let (width, height) = animation.dimensions();
+    let frames: Vec<image::RgbaImage> = animation
+        .frames
+        .iter()
+        .map(|frame| frame.image.to_rgba8())
+        .collect();
+
+    let mut png_data = Vec::new();
     {
         let mut encoder = png::Encoder::new(&mut png_data, width, height);
-        encoder.set_color(png::ColorType::Rgba);
         encoder.set_depth(png::BitDepth::Eight);
         encoder.set_compression(png::Compression::Best);
+        encoder.set_animated(frames.len() as u32, animation.loop_count)?;
+
+        let quantized = if options.lossy {
+            let liq = quantizer(options)?;
+            match quantize_shared_palette(&liq, &frames, options.dithering) {
+                Ok(quantized) => Some(quantized),
+                // Below min_quality the frames are stored losslessly
+                Err(imagequant::Error::QualityTooLow) => None,
+                Err(e) => return Err(e.into()),
+            }
+        } else {
+            None
+        };
+
+        let frame_data = if let Some((palette, indexed)) = quantized {
+            encoder.set_color(png::ColorType::Indexed);
+            encoder.set_palette(
+                palette
+                    .iter()
+                    .flat_map(|rgba| [rgba.r, rgba.g, rgba.b])
+                    .collect::<Vec<u8>>(),
+            );
+            if options.preserve_transparency {
+                encoder.set_trns(palette_alpha(&palette));
+            }
+            indexed
+        } else {
+            encoder.set_color(png::ColorType::Rgba);
+            frames.into_iter().map(|frame| frame.into_raw()).collect()
+        };
 
         let mut writer = encoder.write_header()?;
-        writer.write_image_data(rgba.as_raw())?;
+        for (frame, data) in animation.frames.iter().zip(&frame_data) {
+            writer.set_frame_delay(frame.delay_ms.min(u16::MAX as u32) as u16, 1000)?;
+            writer.write_image_data(data)?;
+        }
+        writer.finish()?;
     }
 
     Ok(png_data)
@@ -93,12 +186,12 @@ fn compress_png_lossless(img: &DynamicImage) -> AppResult<Vec<u8>> {
 // Implement From trait for imagequant errors
 impl From<imagequant::Error> for AppError {
     fn from(err: imagequant::Error) -> Self {
-        AppError::Processing(format!("Imagequant error: {:?}", err))
+        AppError::Encode(format!("Imagequant error: {:?}", err))
     }
 }
 
 impl From<png::EncodingError> for AppError {
     fn from(err: png::EncodingError) -> Self {
-        AppError::Processing(format!("PNG encoding error: {}", err))
+        AppError::Encode(format!("PNG encoding error: {}", err))
     }
 }
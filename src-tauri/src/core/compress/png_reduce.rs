@@ -0,0 +1,357 @@
+use image::RgbaImage;
+use std::collections::{HashMap, HashSet};
+
+const MAX_PALETTE: usize = 256;
+
+/// Pixels re-laid out in the smallest PNG color type that stores them exactly
+pub struct ReducedImage {
+    pub color_type: png::ColorType,
+    pub bit_depth: png::BitDepth,
+    /// Rows packed at `bit_depth`, ready for `write_image_data`
+    pub data: Vec<u8>,
+    pub palette: Option<Vec<u8>>,
+    pub trns: Option<Vec<u8>>,
+}
+
+/// What a pass over the pixels found out
+struct Analysis {
+    opaque: bool,
+    gray: bool,
+    /// The one color used by every transparent pixel, when alpha is binary
+    /// and no opaque pixel shares it, so tRNS can mark it
+    key: Option<[u8; 3]>,
+    /// Distinct colors in first-seen order, `None` past `MAX_PALETTE`
+    colors: Option<Vec<[u8; 4]>>,
+}
+
+/// Picks the color type with the fewest bits per pixel:
+/// grayscale, palette, gray + alpha, RGB or RGBA, with tRNS when one
+/// transparent color is enough
+pub fn reduce_color_type(rgba: &RgbaImage) -> ReducedImage {
+    let analysis = analyze(rgba);
+    let width = rgba.width() as usize;
+
+    let gray_depth = if analysis.gray && (analysis.opaque || analysis.key.is_some()) {
+        gray_bit_depth(rgba, analysis.key)
+    } else {
+        None
+    };
+    let palette_depth = analysis
+        .colors
+        .as_ref()
+        .map(|colors| palette_bit_depth(colors.len()));
+
+    // Ties go to the earlier option, which needs no PLTE chunk
+    let mut best = if analysis.opaque || analysis.key.is_some() {
+        Choice::Rgb
+    } else if analysis.gray {
+        Choice::GrayAlpha
+    } else {
+        Choice::Rgba
+    };
+    if let Some(depth) = palette_depth {
+        if depth as u32 <= best.bits() {
+            best = Choice::Palette(depth);
+        }
+    }
+    if let Some(depth) = gray_depth {
+        if depth as u32 <= best.bits() {
+            best = Choice::Gray(depth);
+        }
+    }
+
+    match best {
+        Choice::Gray(depth) => {
+            let scale = 255 / ((1u16 << depth as u8) - 1) as u8;
+            let levels: Vec<u8> = rgba.pixels().map(|p| p[0] / scale).collect();
+            ReducedImage {
+                color_type: png::ColorType::Grayscale,
+                bit_depth: depth,
+                data: pack_indices(&levels, width, depth),
+                palette: None,
+                trns: analysis.key.map(|[v, _, _]| vec![0, v / scale]),
+            }
+        }
+        Choice::Palette(depth) => {
+            let mut colors = analysis.colors.unwrap_or_default();
+            // Transparent entries first, so trailing opaque ones can be left
+            // out of tRNS
+            colors.sort_by_key(|c| c[3]);
+            let index: HashMap<[u8; 4], u8> = colors
+                .iter()
+                .enumerate()
+                .map(|(i, &c)| (c, i as u8))
+                .collect();
+            let indices: Vec<u8> = rgba.pixels().map(|p| index[&p.0]).collect();
+
+            let alpha_len = colors.iter().take_while(|c| c[3] < 255).count();
+            ReducedImage {
+                color_type: png::ColorType::Indexed,
+                bit_depth: depth,
+                data: pack_indices(&indices, width, depth),
+                palette: Some(colors.iter().flat_map(|c| [c[0], c[1], c[2]]).collect()),
+                trns: (alpha_len > 0).then(|| colors[..alpha_len].iter().map(|c| c[3]).collect()),
+            }
+        }
+        Choice::GrayAlpha => ReducedImage {
+            color_type: png::ColorType::GrayscaleAlpha,
+            bit_depth: png::BitDepth::Eight,
+            data: rgba.pixels().flat_map(|p| [p[0], p[3]]).collect(),
+            palette: None,
+            trns: None,
+        },
+        Choice::Rgb => ReducedImage {
+            color_type: png::ColorType::Rgb,
+            bit_depth: png::BitDepth::Eight,
+            data: rgba.pixels().flat_map(|p| [p[0], p[1], p[2]]).collect(),
+            palette: None,
+            trns: analysis.key.map(|[r, g, b]| vec![0, r, 0, g, 0, b]),
+        },
+        Choice::Rgba => ReducedImage {
+            color_type: png::ColorType::Rgba,
+            bit_depth: png::BitDepth::Eight,
+            data: rgba.as_raw().clone(),
+            palette: None,
+            trns: None,
+        },
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Choice {
+    Gray(png::BitDepth),
+    Palette(png::BitDepth),
+    GrayAlpha,
+    Rgb,
+    Rgba,
+}
+
+impl Choice {
+    fn bits(self) -> u32 {
+        match self {
+            Choice::Gray(depth) | Choice::Palette(depth) => depth as u32,
+            Choice::GrayAlpha => 16,
+            Choice::Rgb => 24,
+            Choice::Rgba => 32,
+        }
+    }
+}
+
+fn analyze(rgba: &RgbaImage) -> Analysis {
+    let mut opaque = true;
+    let mut gray = true;
+    let mut binary_alpha = true;
+    let mut key: Option<[u8; 3]> = None;
+    let mut key_conflict = false;
+    let mut seen = HashSet::new();
+    let mut colors = Some(Vec::new());
+
+    for p in rgba.pixels() {
+        let [r, g, b, a] = p.0;
+        gray &= r == g && g == b;
+        if a < 255 {
+            opaque = false;
+            binary_alpha &= a == 0;
+            match key {
+                None => key = Some([r, g, b]),
+                Some(k) => key_conflict |= k != [r, g, b],
+            }
+        }
+
+        if let Some(list) = colors.as_mut() {
+            if seen.insert(p.0) {
+                list.push(p.0);
+                if list.len() > MAX_PALETTE {
+                    colors = None;
+                }
+            }
+        }
+    }
+
+    // tRNS makes every pixel of the key color transparent, so no opaque
+    // pixel may use it
+    let key = key.filter(|&[r, g, b]| {
+        binary_alpha
+            && !key_conflict
+            && !rgba
+                .pixels()
+                .any(|p| p[3] == 255 && p[0] == r && p[1] == g && p[2] == b)
+    });
+
+    Analysis {
+        opaque,
+        gray,
+        key,
+        colors,
+    }
+}
+
+// The lowest grayscale depth whose levels hit every gray value exactly
+fn gray_bit_depth(rgba: &RgbaImage, key: Option<[u8; 3]>) -> Option<png::BitDepth> {
+    let mut used = [false; 256];
+    for p in rgba.pixels() {
+        used[p[0] as usize] = true;
+    }
+    if let Some([v, _, _]) = key {
+        used[v as usize] = true;
+    }
+
+    [
+        png::BitDepth::One,
+        png::BitDepth::Two,
+        png::BitDepth::Four,
+        png::BitDepth::Eight,
+    ]
+    .into_iter()
+    .find(|&depth| {
+        let scale = 255 / ((1u16 << depth as u8) - 1) as usize;
+        used.iter()
+            .enumerate()
+            .all(|(value, &used)| !used || value % scale == 0)
+    })
+}
+
+/// Bits per index needed for a palette of this many colors
+pub fn palette_bit_depth(colors: usize) -> png::BitDepth {
+    match colors {
+        0..=2 => png::BitDepth::One,
+        3..=4 => png::BitDepth::Two,
+        5..=16 => png::BitDepth::Four,
+        _ => png::BitDepth::Eight,
+    }
+}
+
+/// Packs one-byte samples into rows of the given bit depth, most
+/// significant bits first and each row padded to a whole byte
+pub fn pack_indices(indices: &[u8], width: usize, depth: png::BitDepth) -> Vec<u8> {
+    let bits = depth as usize;
+    if bits == 8 {
+        return indices.to_vec();
+    }
+
+    let per_byte = 8 / bits;
+    let row_bytes = width.div_ceil(per_byte);
+    let mut packed = Vec::with_capacity(row_bytes * indices.len() / width.max(1));
+    for row in indices.chunks(width) {
+        for chunk in row.chunks(per_byte) {
+            let byte = chunk.iter().enumerate().fold(0u8, |byte, (i, &index)| {
+                byte | index << (8 - bits * (i + 1))
+            });
+            packed.push(byte);
+        }
+    }
+    packed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    // Encodes the reduction and decodes it back to RGBA
+    fn round_trip(rgba: &RgbaImage) -> (ReducedImage, RgbaImage) {
+        let reduced = reduce_color_type(rgba);
+        let mut data = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut data, rgba.width(), rgba.height());
+            encoder.set_color(reduced.color_type);
+            encoder.set_depth(reduced.bit_depth);
+            if let Some(palette) = &reduced.palette {
+                encoder.set_palette(palette.as_slice());
+            }
+            if let Some(trns) = &reduced.trns {
+                encoder.set_trns(trns.as_slice());
+            }
+            let mut writer = encoder.write_header().unwrap();
+            writer.write_image_data(&reduced.data).unwrap();
+        }
+        let decoded = image::load_from_memory_with_format(&data, image::ImageFormat::Png)
+            .unwrap()
+            .to_rgba8();
+        (reduced, decoded)
+    }
+
+    fn assert_reduces_to(
+        rgba: &RgbaImage,
+        color_type: png::ColorType,
+        bit_depth: png::BitDepth,
+    ) -> ReducedImage {
+        let (reduced, decoded) = round_trip(rgba);
+        assert_eq!(reduced.color_type, color_type);
+        assert_eq!(reduced.bit_depth, bit_depth);
+        assert!(decoded == *rgba, "pixels changed");
+        reduced
+    }
+
+    fn gray(value: u8) -> Rgba<u8> {
+        Rgba([value, value, value, 255])
+    }
+
+    #[test]
+    fn black_and_white_packs_to_one_bit() {
+        // An odd width, so rows end in a padded byte
+        let rgba = RgbaImage::from_fn(13, 7, |x, y| gray(if (x + y) % 3 == 0 { 255 } else { 0 }));
+        assert_reduces_to(&rgba, png::ColorType::Grayscale, png::BitDepth::One);
+    }
+
+    #[test]
+    fn four_gray_levels_pack_to_two_bits() {
+        let rgba = RgbaImage::from_fn(10, 5, |x, _| gray([0, 85, 170, 255][x as usize % 4]));
+        assert_reduces_to(&rgba, png::ColorType::Grayscale, png::BitDepth::Two);
+    }
+
+    #[test]
+    fn gray_levels_between_steps_stay_eight_bit() {
+        let rgba = RgbaImage::from_fn(16, 16, |x, y| gray((y * 16 + x) as u8));
+        assert_reduces_to(&rgba, png::ColorType::Grayscale, png::BitDepth::Eight);
+    }
+
+    #[test]
+    fn transparent_gray_uses_a_key_color() {
+        let rgba = RgbaImage::from_fn(9, 9, |x, y| match (x + y) % 3 {
+            0 => Rgba([85, 85, 85, 0]),
+            1 => gray(0),
+            _ => gray(255),
+        });
+        let reduced = assert_reduces_to(&rgba, png::ColorType::Grayscale, png::BitDepth::Two);
+        assert_eq!(reduced.trns, Some(vec![0, 1]));
+    }
+
+    #[test]
+    fn few_colors_become_a_palette() {
+        let colors = [
+            Rgba([255, 0, 0, 255]),
+            Rgba([0, 128, 255, 255]),
+            Rgba([10, 200, 30, 128]),
+        ];
+        let rgba = RgbaImage::from_fn(11, 6, |x, y| colors[((x * y + x) % 3) as usize]);
+        let reduced = assert_reduces_to(&rgba, png::ColorType::Indexed, png::BitDepth::Two);
+        // Only the translucent entry needs an alpha value
+        assert_eq!(reduced.trns, Some(vec![128]));
+    }
+
+    #[test]
+    fn many_opaque_colors_drop_alpha() {
+        let rgba = RgbaImage::from_fn(32, 32, |x, y| Rgba([x as u8 * 8, y as u8 * 8, 77, 255]));
+        let reduced = assert_reduces_to(&rgba, png::ColorType::Rgb, png::BitDepth::Eight);
+        assert_eq!(reduced.trns, None);
+    }
+
+    #[test]
+    fn many_translucent_colors_keep_rgba() {
+        let rgba = RgbaImage::from_fn(32, 32, |x, y| Rgba([x as u8 * 8, y as u8 * 8, 77, x as u8]));
+        assert_reduces_to(&rgba, png::ColorType::Rgba, png::BitDepth::Eight);
+    }
+
+    #[test]
+    fn key_color_shared_with_an_opaque_pixel_isnt_used() {
+        // tRNS would hide the opaque black pixel too, so alpha is kept
+        let mut rgba = RgbaImage::from_fn(32, 32, |x, y| match (x + y) % 2 {
+            0 => Rgba([x as u8 * 8, y as u8 * 8, 77, 255]),
+            _ => Rgba([0, 0, 0, 0]),
+        });
+        rgba.put_pixel(0, 0, Rgba([0, 0, 0, 255]));
+        let reduced = assert_reduces_to(&rgba, png::ColorType::Rgba, png::BitDepth::Eight);
+        assert_eq!(reduced.trns, None);
+    }
+}
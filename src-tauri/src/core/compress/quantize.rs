@@ -0,0 +1,52 @@
+use image::RgbaImage;
+use imagequant::{Attributes, Histogram, RGBA};
+
+/// Reinterprets an RGBA buffer as the pixel type imagequant expects
+pub fn as_rgba_pixels(rgba: &RgbaImage) -> &[RGBA] {
+    // imagequant expects &[RGBA<u8>], not &[u8]
+    unsafe {
+        std::slice::from_raw_parts(
+            rgba.as_raw().as_ptr() as *const RGBA,
+            (rgba.width() * rgba.height()) as usize,
+        )
+    }
+}
+
+/// Quantizes several same-sized frames to one palette shared by all of them.
+/// Returns the palette and one index buffer per frame. Errors are left as
+/// imagequant's so callers can handle `QualityTooLow`.
+pub fn quantize_shared_palette(
+    liq: &Attributes,
+    frames: &[RgbaImage],
+    dithering: bool,
+) -> Result<(Vec<RGBA>, Vec<Vec<u8>>), imagequant::Error> {
+    let mut histogram = Histogram::new(liq);
+    for frame in frames {
+        let mut img = liq.new_image(
+            as_rgba_pixels(frame),
+            frame.width() as usize,
+            frame.height() as usize,
+            0.0,
+        )?;
+        histogram.add_image(liq, &mut img)?;
+    }
+
+    // Histogram palettes are frozen, so remapping frames one by one
+    // doesn't change colors between them
+    let mut res = histogram.quantize(liq)?;
+    res.set_dithering_level(if dithering { 1.0 } else { 0.0 })?;
+
+    let mut indexed = Vec::with_capacity(frames.len());
+    for frame in frames {
+        let mut img = liq.new_image(
+            as_rgba_pixels(frame),
+            frame.width() as usize,
+            frame.height() as usize,
+            0.0,
+        )?;
+        let (_, pixels) = res.remapped(&mut img)?;
+        indexed.push(pixels);
+    }
+
+    Ok((res.palette_vec(), indexed))
+}
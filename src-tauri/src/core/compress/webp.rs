@@ -1,24 +1,204 @@
+use crate::core::animation::Animation;
 use crate::core::config::WebPOptions;
-use crate::error::AppResult;
-use image::DynamicImage;
+use crate::error::{AppError, AppResult};
+use image::{DynamicImage, RgbaImage};
+use libwebp_sys::{
+    WebPAnimEncoder, WebPAnimEncoderAdd, WebPAnimEncoderAssemble, WebPAnimEncoderDelete,
+    WebPAnimEncoderGetError, WebPAnimEncoderNewInternal, WebPAnimEncoderOptions,
+    WebPAnimEncoderOptionsInitInternal, WebPConfig, WebPData, WebPDataClear, WebPEncode,
+    WebPEncodeRGBA, WebPFree, WebPGetMuxABIVersion, WebPMemoryWrite, WebPMemoryWriter,
+    WebPMemoryWriterClear, WebPMemoryWriterInit, WebPPicture, WebPPictureFree,
+    WebPPictureImportRGBA, WebPPictureInit,
+};
 
-pub fn compress_webp(img: &DynamicImage, _options: &WebPOptions) -> AppResult<Vec<u8>> {
+/// Encodes a still image with libwebp, lossy at `options.quality` or
+/// lossless, at the configured compression method
+pub fn compress_webp(img: &DynamicImage, options: &WebPOptions) -> AppResult<Vec<u8>> {
+    let config = encoder_config(options)?;
     let rgba = img.to_rgba8();
-    let _width = rgba.width();
-    let _height = rgba.height();
 
-    // Use image crate's WebP support via save_buffer
-    let mut webp_data = Vec::new();
+    // SAFETY: the picture reads from `rgba`, which outlives it, and the
+    // writer's buffer is copied before it is cleared
+    unsafe {
+        let mut picture = std::mem::MaybeUninit::<WebPPicture>::uninit();
+        if !WebPPictureInit(picture.as_mut_ptr()) {
+            return Err(AppError::Encode(
+                "Failed to initialize WebP picture".to_string(),
+            ));
+        }
+        let mut picture = picture.assume_init();
+        picture.use_argb = 1;
+        picture.width = rgba.width() as i32;
+        picture.height = rgba.height() as i32;
 
-    // For now, use a simpler approach - convert to WebP using the image crate
-    // Note: image v0.25 WebP support might be limited, consider using webp crate directly
-    let cursor = std::io::Cursor::new(&mut webp_data);
+        let mut writer = std::mem::MaybeUninit::<WebPMemoryWriter>::uninit();
+        WebPMemoryWriterInit(writer.as_mut_ptr());
+        let mut writer = writer.assume_init();
+        picture.writer = Some(WebPMemoryWrite);
+        picture.custom_ptr = (&mut writer as *mut WebPMemoryWriter).cast();
 
-    // Encode as WebP
-    img.write_to(
-        &mut std::io::BufWriter::new(cursor),
-        image::ImageFormat::WebP,
-    )?;
+        let encoded = WebPPictureImportRGBA(
+            &mut picture,
+            rgba.as_raw().as_ptr(),
+            rgba.width() as i32 * 4,
+        ) != 0
+            && WebPEncode(&config, &mut picture) != 0;
+        let error = picture.error_code;
+        WebPPictureFree(&mut picture);
+
+        let result = if encoded {
+            Ok(std::slice::from_raw_parts(writer.mem, writer.size).to_vec())
+        } else {
+            Err(AppError::Encode(format!(
+                "Failed to encode WebP: {:?}",
+                error
+            )))
+        };
+        WebPMemoryWriterClear(&mut writer);
+        result
+    }
+}
+
+// Settings shared by still and animated encodes
+fn encoder_config(options: &WebPOptions) -> AppResult<WebPConfig> {
+    let mut config = WebPConfig::new()
+        .map_err(|_| AppError::Encode("Failed to initialize WebP config".to_string()))?;
+    config.lossless = (!options.lossy) as i32;
+    config.quality = options.quality.min(100) as f32;
+    config.method = options.method.min(6) as i32;
+    Ok(config)
+}
+
+/// Lossy WebP that keeps the alpha channel, through libwebp's one-call API.
+/// Used for previews of transparent images, which JPEG would flatten.
+pub fn encode_webp_lossy(rgba: &RgbaImage, quality: f32) -> AppResult<Vec<u8>> {
+    let (width, height) = rgba.dimensions();
+    let mut output: *mut u8 = std::ptr::null_mut();
+
+    // SAFETY: the buffer holds width * height RGBA pixels and libwebp's
+    // allocation is copied before it is freed
+    unsafe {
+        let size = WebPEncodeRGBA(
+            rgba.as_ptr(),
+            width as i32,
+            height as i32,
+            (width * 4) as i32,
+            quality.clamp(0.0, 100.0),
+            &mut output,
+        );
+        if size == 0 || output.is_null() {
+            return Err(AppError::Encode("Failed to encode WebP".to_string()));
+        }
+        let data = std::slice::from_raw_parts(output, size).to_vec();
+        WebPFree(output.cast());
+        Ok(data)
+    }
+}
+
+/// Encodes an animation with libwebp's animation encoder, which stores only
+/// the changed sub-rectangle of each frame
+pub fn compress_webp_animated(animation: &Animation, options: &WebPOptions) -> AppResult<Vec<u8>> {
+    let (width, height) = animation.dimensions();
+    if animation.frames.is_empty() {
+        return Err(AppError::Encode("Animation has no frames".to_string()));
+    }
+
+    let config = encoder_config(options)?;
+
+    unsafe {
+        let mut anim_options = std::mem::MaybeUninit::<WebPAnimEncoderOptions>::uninit();
+        if WebPAnimEncoderOptionsInitInternal(anim_options.as_mut_ptr(), WebPGetMuxABIVersion())
+            == 0
+        {
+            return Err(AppError::Encode(
+                "Failed to initialize WebP animation options".to_string(),
+            ));
+        }
+        let mut anim_options = anim_options.assume_init();
+        anim_options.anim_params.loop_count = animation.loop_count as i32;
+        // Let libwebp pick lossless for flat frames when encoding lossy
+        anim_options.allow_mixed = options.lossy as i32;
+
+        let encoder = WebPAnimEncoderNewInternal(
+            width as i32,
+            height as i32,
+            &anim_options,
+            WebPGetMuxABIVersion(),
+        );
+        if encoder.is_null() {
+            return Err(AppError::Encode(
+                "Failed to create WebP animation encoder".to_string(),
+            ));
+        }
+
+        let result = encode_frames(encoder, animation, &config);
+        WebPAnimEncoderDelete(encoder);
+        result
+    }
+}
+
+unsafe fn encode_frames(
+    encoder: *mut WebPAnimEncoder,
+    animation: &Animation,
+    config: &WebPConfig,
+) -> AppResult<Vec<u8>> {
+    let mut timestamp = 0i32;
+
+    for frame in &animation.frames {
+        let rgba = frame.image.to_rgba8();
+
+        let mut picture = std::mem::MaybeUninit::<WebPPicture>::uninit();
+        if !WebPPictureInit(picture.as_mut_ptr()) {
+            return Err(AppError::Encode(
+                "Failed to initialize WebP picture".to_string(),
+            ));
+        }
+        let mut picture = picture.assume_init();
+        picture.use_argb = 1;
+        picture.width = rgba.width() as i32;
+        picture.height = rgba.height() as i32;
+
+        let imported = WebPPictureImportRGBA(
+            &mut picture,
+            rgba.as_raw().as_ptr(),
+            rgba.width() as i32 * 4,
+        );
+        let added =
+            imported != 0 && WebPAnimEncoderAdd(encoder, &mut picture, timestamp, config) != 0;
+        WebPPictureFree(&mut picture);
+
+        if !added {
+            return Err(anim_encoder_error(encoder));
+        }
+        timestamp += frame.delay_ms as i32;
+    }
+
+    // A final empty frame sets the duration of the last real one
+    WebPAnimEncoderAdd(encoder, std::ptr::null_mut(), timestamp, std::ptr::null());
+
+    let mut data = WebPData {
+        bytes: std::ptr::null(),
+        size: 0,
+    };
+    if WebPAnimEncoderAssemble(encoder, &mut data) == 0 {
+        return Err(anim_encoder_error(encoder));
+    }
+
+    let webp_data = std::slice::from_raw_parts(data.bytes, data.size).to_vec();
+    WebPDataClear(&mut data);
 
     Ok(webp_data)
 }
+
+unsafe fn anim_encoder_error(encoder: *mut WebPAnimEncoder) -> AppError {
+    let message = WebPAnimEncoderGetError(encoder);
+    let message = if message.is_null() {
+        "unknown error".to_string()
+    } else {
+        std::ffi::CStr::from_ptr(message)
+            .to_string_lossy()
+            .into_owned()
+    };
+
+    AppError::Encode(format!("WebP animation error: {}", message))
+}
@@ -1,12 +1,16 @@
+use crate::error::ErrorInfo;
 use serde::{Deserialize, Serialize};
 use std::path::PathBuf;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "camelCase")]
 pub enum OutputFormat {
     Png,
     Jpeg,
     WebP,
+    Gif,
+    /// Picks a format per image, see `ProcessResult::format_choice`
+    Auto,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -51,11 +55,17 @@ pub enum PngEncoder {
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[serde(rename_all = "camelCase", default)]
 pub struct PngOptions {
     pub encoder: PngEncoder,
     pub lossy: bool,
-    pub quality: u8, // 0-100, maps to color count
+    pub quality: u8, // 0-100, imagequant target quality
+    /// Below this quality the image is saved losslessly instead
+    pub min_quality: u8,
+    /// 1 (slowest, best palette) to 10 (fastest)
+    pub speed: u8,
+    /// Low bits to drop from each channel (0-4), for displays with less precision
+    pub posterize: u8,
     pub dithering: bool,
     pub preserve_transparency: bool,
 }
@@ -79,6 +89,27 @@ pub struct JpegOptions {
     pub quality: u8, // 0-100
     pub progressive: bool,
     pub optimize_coding: bool, // Optimize Huffman tables
+    /// Rewrite JPEG inputs without decoding pixels when nothing else changes them
+    #[serde(default)]
+    pub lossless: bool,
+    /// Rotation or flip, applied to the coefficients in lossless mode
+    #[serde(default)]
+    pub transform: JpegTransform,
+    /// Drop partial edge blocks that can't be transformed losslessly
+    #[serde(default)]
+    pub trim_edges: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum JpegTransform {
+    #[default]
+    None,
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    FlipHorizontal,
+    FlipVertical,
 }
 
 // WebP Encoder Options
@@ -99,6 +130,118 @@ pub struct WebPOptions {
     pub method: u8,  // 0-6, compression method (0=fast, 6=slower but better)
 }
 
+// GIF Encoder Options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GifOptions {
+    pub quality: u8, // 0-100, imagequant target quality
+    /// One palette for all frames instead of one per frame
+    pub shared_palette: bool,
+    pub dithering: bool,
+    /// Keep (binary) transparency; otherwise alpha is flattened
+    pub preserve_transparency: bool,
+    /// Store only the changed region of each frame
+    pub optimize_frames: bool,
+}
+
+// SVG Rasterization Options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SvgOptions {
+    /// Render resolution; 96 renders at the document's CSS pixel size
+    pub dpi: f32,
+    /// Hex color painted behind the drawing, transparent when unset
+    pub background: Option<String>,
+}
+
+/// Caps checked against image headers before decoding, so a small file
+/// claiming huge dimensions fails cleanly instead of exhausting memory
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DecodeLimits {
+    pub max_width: u32,
+    pub max_height: u32,
+    /// Width times height
+    pub max_pixels: u64,
+    /// Largest allocation a decoder may make, and the total for all frames
+    /// of an animation, in MiB
+    pub max_alloc_mb: u64,
+}
+
+// Adjustments
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SharpenOptions {
+    pub amount: f32,   // percent of the detail added back, 0-500
+    pub radius: f32,   // blur sigma in pixels
+    pub threshold: u8, // 0-255, smaller differences are left alone
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct AdjustmentOptions {
+    /// Unsharp mask, off when unset
+    pub sharpen: Option<SharpenOptions>,
+    pub brightness: f32, // -100 to 100
+    pub contrast: f32,   // -100 to 100
+    pub saturation: f32, // -100 to 100, -100 removes all color
+    pub gamma: f32,      // 0.1-10, 1 leaves midtones unchanged
+    pub grayscale: bool,
+}
+
+// Alpha Handling
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum TransparencyPolicy {
+    /// Composite onto the matte color silently
+    Flatten,
+    /// Composite onto the matte color and report it in the result
+    Warn,
+    /// Fail instead of losing transparency
+    Refuse,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MatteOptions {
+    /// Hex color transparent pixels are composited onto
+    pub color: String,
+    /// What to do when a transparent input goes to a format without alpha
+    pub on_transparency: TransparencyPolicy,
+}
+
+// Watermark Options
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum WatermarkSource {
+    /// Logo file, usually a PNG with transparency
+    Image { path: PathBuf },
+    /// Text rendered with the bundled DejaVu Sans Bold font
+    Text { text: String, color: String },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum WatermarkPosition {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WatermarkOptions {
+    pub source: WatermarkSource,
+    pub position: WatermarkPosition,
+    pub margin: f32, // percent of the shorter output side
+    pub scale: f32,  // watermark width as percent of the output width
+    pub opacity: u8, // 0-100
+    /// Repeat the watermark diagonally across the image, ignoring `position`
+    pub tiled: bool,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub enum MetadataMode {
@@ -125,11 +268,76 @@ pub struct ProcessConfig {
     pub png_options: PngOptions,
     pub jpeg_options: JpegOptions,
     pub webp_options: WebPOptions,
+    #[serde(default)]
+    pub gif_options: GifOptions,
+    #[serde(default)]
+    pub svg_options: SvgOptions,
+    /// Applied after resizing, before the watermark
+    #[serde(default)]
+    pub adjustments: AdjustmentOptions,
+    /// Background for outputs that can't store alpha
+    #[serde(default)]
+    pub matte: MatteOptions,
+    /// Overlay applied after resizing, none when unset
+    #[serde(default)]
+    pub watermark: Option<WatermarkOptions>,
     pub metadata_mode: MetadataMode,
     pub file_conflict_mode: FileConflictMode,
     pub preserve_folder_structure: bool,
     pub base_path: Option<PathBuf>,
     pub output_path: PathBuf,
+    /// Skip inputs whose content and settings match a previous run
+    #[serde(default)]
+    pub incremental: bool,
+    /// Give outputs the extension of the format they were written in when
+    /// the generated name says otherwise. Inputs are never renamed.
+    #[serde(default)]
+    pub fix_output_extension: bool,
+    #[serde(default)]
+    pub limits: DecodeLimits,
+    /// Replace inputs with their optimized versions instead of writing to
+    /// `output_path`, keeping the originals in a backup run
+    #[serde(default)]
+    pub in_place: Option<InPlaceOptions>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InPlaceOptions {
+    /// Run folder from `begin_in_place_run` that receives the originals
+    pub backup_dir: PathBuf,
+}
+
+// Directory Scanning
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SymlinkPolicy {
+    /// Ignore symlinked files and directories
+    Skip,
+    /// Include symlinked files but don't descend into symlinked directories
+    FilesOnly,
+    /// Follow all symlinks, skipping any that form a loop
+    Follow,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct ScanOptions {
+    /// Glob patterns relative to the scanned folder; empty means everything
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    /// Honor .gitignore and .ignore files
+    pub respect_ignore_files: bool,
+    pub skip_hidden: bool,
+    /// Skip folders such as .git and node_modules
+    pub skip_system_folders: bool,
+    pub min_file_size: Option<u64>,
+    pub max_file_size: Option<u64>,
+    pub min_width: Option<u32>,
+    pub min_height: Option<u32>,
+    pub max_width: Option<u32>,
+    pub max_height: Option<u32>,
+    pub symlinks: SymlinkPolicy,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -141,6 +349,34 @@ pub struct FileInfo {
     pub width: u32,
     pub height: u32,
     pub format: String,
+    /// Format sniffed from the file contents
+    pub detected_format: Option<String>,
+    /// Lowercased file extension, if any
+    pub extension: Option<String>,
+    /// True when the extension doesn't match the detected format
+    pub extension_mismatch: bool,
+    #[serde(flatten)]
+    pub details: ImageDetails,
+}
+
+/// Header-level properties read without decoding the pixels
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageDetails {
+    pub has_alpha: bool,
+    /// Bits per channel
+    pub bit_depth: Option<u8>,
+    /// Gray, GrayAlpha, RGB, RGBA, Indexed or CMYK
+    pub color_type: Option<String>,
+    pub frame_count: u32,
+    pub animated: bool,
+    /// Description of the embedded ICC profile
+    pub icc_profile: Option<String>,
+    /// EXIF orientation (1-8)
+    pub orientation: Option<u16>,
+    pub dpi: Option<f32>,
+    /// EXIF capture date as ISO 8601 local time
+    pub capture_date: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -151,7 +387,122 @@ pub struct ProcessResult {
     pub new_size: u64,
     pub compression_ratio: f32,
     pub output_path: String,
-    pub error: Option<String>,
+    pub error: Option<ErrorInfo>,
+    /// True when the output was reused from a previous run
+    #[serde(default)]
+    pub cached: bool,
+    /// True when an in-place run kept the original because the optimized
+    /// file wasn't smaller
+    #[serde(default)]
+    pub unchanged: bool,
+    /// Non-fatal issues, such as transparency lost to the output format
+    #[serde(default)]
+    pub warnings: Vec<String>,
+    /// Format picked for `OutputFormat::Auto` and why
+    #[serde(default)]
+    pub format_choice: Option<FormatChoice>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FormatChoice {
+    pub format: OutputFormat,
+    pub reason: String,
+}
+
+/// The outcome of a dry run: what processing would produce, without writing
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompressionPreview {
+    /// Base64 of the encoded output, or of a lossless downscale when it is
+    /// larger than the requested preview size
+    pub preview: String,
+    pub mime_type: String,
+    /// True when `preview` is the exact output rather than a downscale
+    pub exact: bool,
+    pub original_size: u64,
+    pub new_size: u64,
+    pub compression_ratio: f32,
+    pub width: u32,
+    pub height: u32,
+    pub format: OutputFormat,
+    pub format_choice: Option<FormatChoice>,
+    pub warnings: Vec<String>,
+    /// Difference from the original, when requested
+    pub comparison: Option<ImageComparison>,
+}
+
+/// Differences between an original and its processed version
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ImageComparison {
+    /// Base64 PNG coloring each pixel by its largest channel error
+    pub heatmap: String,
+    /// Size the comparison was made at, the processed image's
+    pub width: u32,
+    pub height: u32,
+    /// True when the original was scaled to match
+    pub resized: bool,
+    pub red: ChannelError,
+    pub green: ChannelError,
+    pub blue: ChannelError,
+    pub alpha: ChannelError,
+    /// Peak signal-to-noise ratio in dB over RGB, none when identical
+    pub psnr: Option<f64>,
+    /// Areas with the highest mean error, worst first
+    pub regions: Vec<ErrorRegion>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ChannelError {
+    pub mean: f64,
+    pub max: u8,
+    pub rmse: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub mean_error: f64,
+    pub max_error: u8,
+}
+
+/// How a batch shares the machine. Doesn't affect the output, so it's
+/// passed next to `ProcessConfig` rather than in it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct BatchOptions {
+    /// Images decoded and encoded at once, 0 for one per CPU core
+    pub workers: usize,
+    /// Estimated memory all images in flight may use together, in MiB.
+    /// An image larger than this still runs, on its own.
+    pub memory_limit_mb: u32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BatchSummary {
+    pub results: Vec<ProcessResult>,
+    pub processed: usize,
+    pub cached: usize,
+    pub failed: usize,
+    /// True when the output disk filled up and the remaining files were skipped
+    pub stopped: bool,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UndoSummary {
+    /// Originals moved back into place
+    pub restored: usize,
+    /// Files that couldn't be restored; their backups are kept so the undo
+    /// can be retried
+    pub errors: Vec<ErrorInfo>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -165,6 +516,22 @@ pub enum ProgressEvent {
     Error(String),
 }
 
+impl Default for ImageDetails {
+    fn default() -> Self {
+        Self {
+            has_alpha: false,
+            bit_depth: None,
+            color_type: None,
+            frame_count: 1,
+            animated: false,
+            icc_profile: None,
+            orientation: None,
+            dpi: None,
+            capture_date: None,
+        }
+    }
+}
+
 impl Default for ResizeConfig {
     fn default() -> Self {
         Self {
@@ -181,6 +548,9 @@ impl Default for PngOptions {
             encoder: PngEncoder::Imagequant, // Best for lossy compression
             lossy: true,                     // Enable lossy for better compression
             quality: 80,                     // Reduce from 85 to 80
+            min_quality: 0,
+            speed: 4,
+            posterize: 0,
             dithering: true,
             preserve_transparency: true,
         }
@@ -194,6 +564,9 @@ impl Default for JpegOptions {
             quality: 60, // Reduced to 60 to avoid enlarging already-compressed JPGs
             progressive: true,
             optimize_coding: true,
+            lossless: false,
+            transform: JpegTransform::None,
+            trim_edges: false,
         }
     }
 }
@@ -208,3 +581,101 @@ impl Default for WebPOptions {
         }
     }
 }
+
+impl Default for GifOptions {
+    fn default() -> Self {
+        Self {
+            quality: 80,
+            shared_palette: true,
+            dithering: true,
+            preserve_transparency: true,
+            optimize_frames: true,
+        }
+    }
+}
+
+impl Default for SvgOptions {
+    fn default() -> Self {
+        Self {
+            dpi: 96.0,
+            background: None,
+        }
+    }
+}
+
+impl Default for AdjustmentOptions {
+    fn default() -> Self {
+        Self {
+            sharpen: None,
+            brightness: 0.0,
+            contrast: 0.0,
+            saturation: 0.0,
+            gamma: 1.0,
+            grayscale: false,
+        }
+    }
+}
+
+impl Default for DecodeLimits {
+    fn default() -> Self {
+        Self {
+            max_width: 32768,
+            max_height: 32768,
+            max_pixels: 250_000_000,
+            max_alloc_mb: 2048,
+        }
+    }
+}
+
+impl DecodeLimits {
+    /// Limits for thumbnails and previews the webview requests on its own,
+    /// without the user's settings. Several of those decode at once, so
+    /// each gets about a quarter of the default memory budget.
+    pub fn preview() -> Self {
+        Self {
+            max_width: 16384,
+            max_height: 16384,
+            max_pixels: 64_000_000,
+            max_alloc_mb: 512,
+        }
+    }
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            workers: 0,
+            memory_limit_mb: 2048,
+        }
+    }
+}
+
+impl Default for MatteOptions {
+    fn default() -> Self {
+        Self {
+            color: "#ffffff".to_string(),
+            on_transparency: TransparencyPolicy::Flatten,
+        }
+    }
+}
+
+// Every filter is opt-in, so a scan without options lists every supported
+// image it reaches
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            include: Vec::new(),
+            exclude: Vec::new(),
+            respect_ignore_files: false,
+            skip_hidden: false,
+            skip_system_folders: false,
+            min_file_size: None,
+            max_file_size: None,
+            min_width: None,
+            min_height: None,
+            max_width: None,
+            max_height: None,
+            symlinks: SymlinkPolicy::Skip,
+        }
+    }
+}
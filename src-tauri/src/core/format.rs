@@ -0,0 +1,170 @@
+use crate::core::config::{DecodeLimits, SvgOptions};
+use crate::core::jpeg_decode::{decode_jpeg_scaled, scale_denominator};
+use crate::core::svg::{is_svg, render_svg};
+use crate::error::{AppError, AppResult, ResultExt};
+use image::{ImageDecoder, ImageFormat};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+// Enough bytes for every signature image::guess_format knows about
+const SNIFF_LEN: usize = 64;
+
+const MIB: u64 = 1024 * 1024;
+
+/// Detects the image format from the file's magic bytes, ignoring its name
+pub fn detect_format(path: &Path) -> AppResult<Option<ImageFormat>> {
+    let mut file = std::fs::File::open(path).with_path(path)?;
+    let mut header = Vec::with_capacity(SNIFF_LEN);
+    file.by_ref()
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut header)
+        .with_path(path)?;
+
+    Ok(image::guess_format(&header).ok())
+}
+
+/// Whether the file is an SVG document. Raster signatures are checked
+/// first, so a PNG or JPEG mentioning `<svg` near its start, e.g. in XMP
+/// metadata, is still decoded as what it is.
+pub fn is_svg_document(path: &Path) -> AppResult<bool> {
+    Ok(detect_format(path)?.is_none() && is_svg(path))
+}
+
+/// Opens an image by its contents rather than its extension.
+/// SVG documents are rendered at their intrinsic size.
+pub fn open_image(path: &Path, limits: &DecodeLimits) -> AppResult<image::DynamicImage> {
+    if is_svg_document(path)? {
+        return render_svg(path, &SvgOptions::default(), limits);
+    }
+
+    // Only the header is parsed here, so oversized images are rejected,
+    // with their size in the message, before the pixels are allocated
+    let mut decoder = header_reader(path)?
+        .into_decoder()
+        .map_err(limit_error)
+        .with_path(path)?;
+    let (width, height) = decoder.dimensions();
+    check_limits(width, height, limits).with_path(path)?;
+
+    // 16-bit and float images need more than the pixel count suggests
+    let needed = decoder.total_bytes();
+    if needed > limits.max_alloc_mb.saturating_mul(MIB) {
+        return Err(AppError::LimitExceeded(format!(
+            "decoding needs {} MiB, more than the {} MiB limit",
+            needed.div_ceil(MIB),
+            limits.max_alloc_mb
+        ))
+        .with_path(path));
+    }
+    decoder
+        .set_limits(image_limits(limits))
+        .map_err(limit_error)
+        .with_path(path)?;
+
+    image::DynamicImage::from_decoder(decoder)
+        .map_err(limit_error)
+        .with_path(path)
+}
+
+/// Opens an image that is about to be shrunk. `target` gets the full size
+/// and returns the smallest size the caller needs; JPEGs are decoded at
+/// 1/2, 1/4 or 1/8 scale when that still covers it. Returns the image
+/// along with its full size.
+pub fn open_image_scaled(
+    path: &Path,
+    limits: &DecodeLimits,
+    target: impl FnOnce(u32, u32) -> (u32, u32),
+) -> AppResult<(image::DynamicImage, (u32, u32))> {
+    if detect_format(path)? == Some(ImageFormat::Jpeg) {
+        let (width, height) = header_reader(path)?.into_dimensions().with_path(path)?;
+        check_limits(width, height, limits).with_path(path)?;
+
+        let (min_width, min_height) = target(width, height);
+        let denominator = scale_denominator(width, height, min_width, min_height);
+        if denominator > 1 {
+            let data = std::fs::read(path).with_path(path)?;
+            if let Some(img) = decode_jpeg_scaled(&data, denominator).with_path(path)? {
+                return Ok((img, (width, height)));
+            }
+        }
+    }
+
+    let img = open_image(path, limits)?;
+    let size = (img.width(), img.height());
+    Ok((img, size))
+}
+
+/// Opens a reader for header-only reads such as dimensions or the color
+/// type. Only the allocation cap applies, so oversized images still
+/// report their size.
+pub fn header_reader(path: &Path) -> AppResult<image::ImageReader<BufReader<File>>> {
+    let mut reader = image::ImageReader::open(path)
+        .with_path(path)?
+        .with_guessed_format()
+        .with_path(path)?;
+
+    let mut limits = image::Limits::default();
+    limits.max_alloc = Some(DecodeLimits::default().max_alloc_mb.saturating_mul(MIB));
+    reader.limits(limits);
+    Ok(reader)
+}
+
+/// Fails with `AppError::LimitExceeded` when the dimensions are over the limits
+pub fn check_limits(width: u32, height: u32, limits: &DecodeLimits) -> AppResult<()> {
+    if width > limits.max_width || height > limits.max_height {
+        return Err(AppError::LimitExceeded(format!(
+            "{}x{} is larger than the {}x{} limit",
+            width, height, limits.max_width, limits.max_height
+        )));
+    }
+
+    let pixels = u64::from(width) * u64::from(height);
+    if pixels > limits.max_pixels {
+        return Err(AppError::LimitExceeded(format!(
+            "{:.1} megapixels is more than the {:.1} megapixel limit",
+            pixels as f64 / 1e6,
+            limits.max_pixels as f64 / 1e6
+        )));
+    }
+
+    Ok(())
+}
+
+fn image_limits(limits: &DecodeLimits) -> image::Limits {
+    let mut image_limits = image::Limits::default();
+    image_limits.max_image_width = Some(limits.max_width);
+    image_limits.max_image_height = Some(limits.max_height);
+    image_limits.max_alloc = Some(limits.max_alloc_mb.saturating_mul(MIB));
+    image_limits
+}
+
+/// Turns the image crate's limit errors into `AppError::LimitExceeded`
+pub fn limit_error(err: image::ImageError) -> AppError {
+    match err {
+        image::ImageError::Limits(e) => AppError::LimitExceeded(e.to_string()),
+        other => AppError::Image(other),
+    }
+}
+
+pub fn format_name(format: ImageFormat) -> String {
+    match format {
+        ImageFormat::Png => "PNG".to_string(),
+        ImageFormat::Jpeg => "JPEG".to_string(),
+        ImageFormat::WebP => "WEBP".to_string(),
+        ImageFormat::Gif => "GIF".to_string(),
+        ImageFormat::Bmp => "BMP".to_string(),
+        ImageFormat::Tiff => "TIFF".to_string(),
+        other => format!("{:?}", other).to_uppercase(),
+    }
+}
+
+/// Preferred file extension for a detected format
+pub fn canonical_extension(format: ImageFormat) -> &'static str {
+    format.extensions_str().first().copied().unwrap_or("img")
+}
+
+/// Whether the extension is one of the names used for the format
+pub fn extension_matches(ext: Option<&str>, format: ImageFormat) -> bool {
+    ext.is_some_and(|ext| ImageFormat::from_extension(ext) == Some(format))
+}
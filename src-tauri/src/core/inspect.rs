@@ -0,0 +1,232 @@
+use crate::core::config::ImageDetails;
+use crate::core::format::header_reader;
+use crate::core::metadata::{icc_profile_description, read_exif_summary};
+use image::{ExtendedColorType, ImageDecoder, ImageFormat};
+use std::io::{BufReader, Read};
+use std::path::Path;
+
+/// Header-level inspection of an image. Never decodes pixel data; fields
+/// that can't be determined are left at their defaults.
+pub fn inspect_image(path: &Path, format: ImageFormat) -> ImageDetails {
+    let mut details = ImageDetails::default();
+
+    inspect_with_decoder(path, &mut details);
+
+    match format {
+        ImageFormat::Png => inspect_png(path, &mut details),
+        ImageFormat::Jpeg => inspect_jpeg(path, &mut details),
+        ImageFormat::Gif => inspect_gif(path, &mut details),
+        ImageFormat::WebP => inspect_webp(path, &mut details),
+        _ => None,
+    };
+
+    if let Some(exif) = read_exif_summary(path) {
+        details.orientation = exif.orientation;
+        details.dpi = exif.dpi.or(details.dpi);
+        details.capture_date = exif.capture_date;
+    }
+
+    details
+}
+
+/// Number of frames in an animated PNG, GIF or WebP, 1 for everything
+/// else. Reads headers only, like `inspect_image`.
+pub fn frame_count(path: &Path, format: ImageFormat) -> u32 {
+    let mut details = ImageDetails::default();
+    match format {
+        ImageFormat::Png => inspect_png(path, &mut details),
+        ImageFormat::Gif => inspect_gif(path, &mut details),
+        ImageFormat::WebP => inspect_webp(path, &mut details),
+        _ => None,
+    };
+    details.frame_count
+}
+
+// Color type, bit depth and ICC profile as reported by the image crate
+fn inspect_with_decoder(path: &Path, details: &mut ImageDetails) -> Option<()> {
+    let mut decoder = header_reader(path).ok()?.into_decoder().ok()?;
+
+    let color = decoder.original_color_type();
+    details.has_alpha = decoder.color_type().has_alpha();
+    details.color_type = color_type_name(color).map(str::to_string);
+    details.bit_depth = Some((color.bits_per_pixel() / color.channel_count().max(1) as u16) as u8);
+
+    if let Ok(Some(icc)) = decoder.icc_profile() {
+        details.icc_profile = icc_profile_description(&icc);
+    }
+
+    Some(())
+}
+
+fn color_type_name(color: ExtendedColorType) -> Option<&'static str> {
+    use ExtendedColorType::*;
+
+    match color {
+        A8 => Some("Alpha"),
+        L1 | L2 | L4 | L8 | L16 => Some("Gray"),
+        La1 | La2 | La4 | La8 | La16 => Some("GrayAlpha"),
+        Rgb1 | Rgb2 | Rgb4 | Rgb8 | Rgb16 | Bgr8 | Rgb32F => Some("RGB"),
+        Rgba1 | Rgba2 | Rgba4 | Rgba8 | Rgba16 | Bgra8 | Rgba32F => Some("RGBA"),
+        Cmyk8 | Cmyk16 => Some("CMYK"),
+        _ => None,
+    }
+}
+
+// The png crate exposes palette, tRNS, APNG and pHYs details the image crate hides
+fn inspect_png(path: &Path, details: &mut ImageDetails) -> Option<()> {
+    let file = std::fs::File::open(path).ok()?;
+    let reader = png::Decoder::new(BufReader::new(file)).read_info().ok()?;
+    let info = reader.info();
+
+    details.bit_depth = Some(info.bit_depth as u8);
+    details.has_alpha = info.color_type == png::ColorType::Rgba
+        || info.color_type == png::ColorType::GrayscaleAlpha
+        || info.trns.is_some();
+    details.color_type = Some(
+        match info.color_type {
+            png::ColorType::Grayscale => "Gray",
+            png::ColorType::GrayscaleAlpha => "GrayAlpha",
+            png::ColorType::Rgb => "RGB",
+            png::ColorType::Rgba => "RGBA",
+            png::ColorType::Indexed => "Indexed",
+        }
+        .to_string(),
+    );
+
+    if let Some(actl) = &info.animation_control {
+        details.frame_count = actl.num_frames.max(1);
+        details.animated = actl.num_frames > 1;
+    }
+
+    if let Some(dims) = &info.pixel_dims {
+        if dims.unit == png::Unit::Meter {
+            details.dpi = Some((dims.xppu as f32 * 0.0254).round());
+        }
+    }
+
+    Some(())
+}
+
+// Walks the JPEG markers up to the first scan: SOF carries the real
+// component count (CMYK is converted to RGB by the decoder) and JFIF the density
+fn inspect_jpeg(path: &Path, details: &mut ImageDetails) -> Option<()> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut reader = BufReader::new(file);
+
+    let mut marker = [0u8; 2];
+    reader.read_exact(&mut marker).ok()?;
+    if marker != [0xFF, 0xD8] {
+        return None;
+    }
+
+    loop {
+        reader.read_exact(&mut marker).ok()?;
+        if marker[0] != 0xFF {
+            return None;
+        }
+        let code = marker[1];
+
+        // Standalone markers and fill bytes carry no length
+        if code == 0xFF || code == 0x01 || (0xD0..=0xD7).contains(&code) {
+            continue;
+        }
+        if code == 0xDA || code == 0xD9 {
+            return Some(());
+        }
+
+        let mut len = [0u8; 2];
+        reader.read_exact(&mut len).ok()?;
+        let len = u16::from_be_bytes(len).checked_sub(2)? as usize;
+        let mut segment = vec![0u8; len];
+        reader.read_exact(&mut segment).ok()?;
+
+        match code {
+            // SOF0..SOF15 except DHT (C4), JPG (C8) and DAC (CC)
+            0xC0..=0xCF if code != 0xC4 && code != 0xC8 && code != 0xCC => {
+                let precision = *segment.first()?;
+                let components = *segment.get(5)?;
+                details.bit_depth = Some(precision);
+                details.color_type = match components {
+                    1 => Some("Gray".to_string()),
+                    3 => Some("RGB".to_string()),
+                    4 => Some("CMYK".to_string()),
+                    _ => details.color_type.take(),
+                };
+            }
+            // APP0 JFIF: units, x density, y density
+            0xE0 if segment.starts_with(b"JFIF\0") && segment.len() >= 12 => {
+                let density = u16::from_be_bytes([segment[8], segment[9]]) as f32;
+                details.dpi = match segment[7] {
+                    1 => Some(density),
+                    2 => Some((density * 2.54).round()),
+                    _ => details.dpi,
+                };
+            }
+            _ => {}
+        }
+    }
+}
+
+fn inspect_gif(path: &Path, details: &mut ImageDetails) -> Option<()> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut options = gif::DecodeOptions::new();
+    // Count frames and read their headers without LZW-decoding them
+    options.skip_frame_decoding(true);
+    let mut decoder = options.read_info(BufReader::new(file)).ok()?;
+
+    let mut frames = 0;
+    let mut has_transparency = false;
+    while let Ok(Some(frame)) = decoder.read_next_frame() {
+        frames += 1;
+        has_transparency |= frame.transparent.is_some();
+    }
+
+    details.color_type = Some("Indexed".to_string());
+    details.bit_depth = Some(8);
+    details.has_alpha = has_transparency;
+    details.frame_count = frames.max(1);
+    details.animated = frames > 1;
+
+    Some(())
+}
+
+// Counts ANMF chunks in the RIFF container; VP8X flags carry alpha
+fn inspect_webp(path: &Path, details: &mut ImageDetails) -> Option<()> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut reader = BufReader::new(file);
+
+    let mut header = [0u8; 12];
+    reader.read_exact(&mut header).ok()?;
+    if &header[0..4] != b"RIFF" || &header[8..12] != b"WEBP" {
+        return None;
+    }
+
+    let mut frames = 0;
+    let mut chunk = [0u8; 8];
+    while reader.read_exact(&mut chunk).is_ok() {
+        let size = u32::from_le_bytes([chunk[4], chunk[5], chunk[6], chunk[7]]) as u64;
+        let padded = size + (size & 1);
+
+        match &chunk[0..4] {
+            b"VP8X" => {
+                let mut flags = [0u8; 1];
+                reader.read_exact(&mut flags).ok()?;
+                details.has_alpha |= flags[0] & 0x10 != 0;
+                reader.seek_relative(padded.checked_sub(1)? as i64).ok()?;
+            }
+            fourcc => {
+                if fourcc == b"ANMF" {
+                    frames += 1;
+                }
+                reader.seek_relative(padded as i64).ok()?;
+            }
+        }
+    }
+
+    if frames > 0 {
+        details.frame_count = frames;
+        details.animated = frames > 1;
+    }
+
+    Some(())
+}
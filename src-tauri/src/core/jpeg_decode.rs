@@ -0,0 +1,89 @@
+use crate::core::compress::jpeg_lossless::{error_manager, panic_message};
+use crate::error::{AppError, AppResult};
+use image::{DynamicImage, GrayImage, RgbImage};
+use mozjpeg_sys::*;
+use std::ffi::c_ulong;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+
+// Reductions libjpeg can apply while decoding, largest first
+const DENOMINATORS: [u32; 3] = [8, 4, 2];
+
+/// The largest DCT-domain reduction that still leaves at least
+/// `min_width` x `min_height` pixels, or 1 when none does
+pub fn scale_denominator(width: u32, height: u32, min_width: u32, min_height: u32) -> u32 {
+    DENOMINATORS
+        .into_iter()
+        .find(|&d| width.div_ceil(d) >= min_width && height.div_ceil(d) >= min_height)
+        .unwrap_or(1)
+}
+
+/// Decodes a JPEG at 1/`denominator` of its size. libjpeg scales the inverse
+/// DCT itself, so most of the decoding work for the skipped pixels is never
+/// done. Returns `None` for CMYK and other color spaces the image crate
+/// converts better, and for damaged files.
+pub fn decode_jpeg_scaled(data: &[u8], denominator: u32) -> AppResult<Option<DynamicImage>> {
+    let mut err = error_manager();
+    // SAFETY: see `error_manager`; the struct is destroyed before `err`
+    unsafe {
+        let mut cinfo: jpeg_decompress_struct = mem::zeroed();
+        cinfo.common.err = &mut err;
+        jpeg_create_decompress(&mut cinfo);
+
+        let result = panic::catch_unwind(AssertUnwindSafe(|| {
+            decompress(&mut cinfo, data, denominator)
+        }));
+        jpeg_destroy_decompress(&mut cinfo);
+
+        // libjpeg pads truncated or corrupt data with gray and only warns;
+        // the regular decoder decides how to handle those files instead
+        if err.num_warnings > 0 {
+            return Ok(None);
+        }
+
+        result.map_err(|payload| {
+            AppError::Decode(format!(
+                "JPEG decoding failed: {}",
+                panic_message(&*payload)
+            ))
+        })
+    }
+}
+
+unsafe fn decompress(
+    cinfo: &mut jpeg_decompress_struct,
+    data: &[u8],
+    denominator: u32,
+) -> Option<DynamicImage> {
+    jpeg_mem_src(cinfo, data.as_ptr(), data.len() as c_ulong);
+    jpeg_read_header(cinfo, 1);
+
+    cinfo.out_color_space = match cinfo.jpeg_color_space {
+        J_COLOR_SPACE::JCS_GRAYSCALE => J_COLOR_SPACE::JCS_GRAYSCALE,
+        J_COLOR_SPACE::JCS_YCbCr | J_COLOR_SPACE::JCS_RGB => J_COLOR_SPACE::JCS_RGB,
+        _ => return None,
+    };
+    cinfo.scale_num = 1;
+    cinfo.scale_denom = denominator;
+    cinfo.dct_method = J_DCT_METHOD::JDCT_ISLOW;
+
+    jpeg_start_decompress(cinfo);
+    let (width, height) = (cinfo.output_width, cinfo.output_height);
+    let row_len = width as usize * cinfo.output_components as usize;
+
+    let mut pixels = vec![0u8; row_len * height as usize];
+    while cinfo.output_scanline < height {
+        let mut row = pixels
+            .as_mut_ptr()
+            .add(cinfo.output_scanline as usize * row_len);
+        jpeg_read_scanlines(cinfo, &mut row, 1);
+    }
+    jpeg_finish_decompress(cinfo);
+
+    match cinfo.out_color_space {
+        J_COLOR_SPACE::JCS_GRAYSCALE => {
+            GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8)
+        }
+        _ => RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8),
+    }
+}
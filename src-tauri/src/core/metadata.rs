@@ -1,8 +1,137 @@
 use crate::error::AppResult;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 pub fn strip_metadata(_file_path: &PathBuf) -> AppResult<()> {
     // TODO: Implement metadata stripping using kamadak-exif and img-parts
     // For now, this is a placeholder
     Ok(())
 }
+
+/// The EXIF fields shown in the file list
+#[derive(Debug, Default, Clone)]
+pub struct ExifSummary {
+    pub orientation: Option<u16>,
+    pub dpi: Option<f32>,
+    pub capture_date: Option<String>,
+}
+
+pub fn read_exif_summary(path: &Path) -> Option<ExifSummary> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut reader = std::io::BufReader::new(file);
+    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+
+    let orientation = exif
+        .get_field(exif::Tag::Orientation, exif::In::PRIMARY)
+        .and_then(|f| f.value.get_uint(0))
+        .map(|v| v as u16);
+
+    let dpi = exif
+        .get_field(exif::Tag::XResolution, exif::In::PRIMARY)
+        .and_then(|f| match &f.value {
+            exif::Value::Rational(v) => v.first().map(|r| r.to_f64()),
+            _ => None,
+        })
+        .map(|res| {
+            // ResolutionUnit 3 means centimeters, anything else inches
+            let unit = exif
+                .get_field(exif::Tag::ResolutionUnit, exif::In::PRIMARY)
+                .and_then(|f| f.value.get_uint(0));
+            if unit == Some(3) {
+                (res * 2.54) as f32
+            } else {
+                res as f32
+            }
+        });
+
+    let capture_date = [exif::Tag::DateTimeOriginal, exif::Tag::DateTime]
+        .into_iter()
+        .find_map(|tag| {
+            let field = exif.get_field(tag, exif::In::PRIMARY)?;
+            match &field.value {
+                exif::Value::Ascii(v) => {
+                    let dt = exif::DateTime::from_ascii(v.first()?).ok()?;
+                    Some(format!(
+                        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+                        dt.year, dt.month, dt.day, dt.hour, dt.minute, dt.second
+                    ))
+                }
+                _ => None,
+            }
+        });
+
+    Some(ExifSummary {
+        orientation,
+        dpi,
+        capture_date,
+    })
+}
+
+/// The JPEG preview cameras store in the EXIF thumbnail directory (IFD1),
+/// if there is one
+pub fn read_embedded_thumbnail(path: &Path) -> Option<Vec<u8>> {
+    let file = std::fs::File::open(path).ok()?;
+    let mut reader = std::io::BufReader::new(file);
+    let exif = exif::Reader::new().read_from_container(&mut reader).ok()?;
+
+    // Both values are relative to the start of the TIFF structure, which
+    // is what `buf` holds
+    let offset = exif
+        .get_field(exif::Tag::JPEGInterchangeFormat, exif::In::THUMBNAIL)
+        .and_then(|f| f.value.get_uint(0))? as usize;
+    let length = exif
+        .get_field(exif::Tag::JPEGInterchangeFormatLength, exif::In::THUMBNAIL)
+        .and_then(|f| f.value.get_uint(0))? as usize;
+
+    exif.buf()
+        .get(offset..offset.checked_add(length)?)
+        .map(<[u8]>::to_vec)
+}
+
+/// Extracts the human-readable name from an ICC profile's `desc` tag
+pub fn icc_profile_description(icc: &[u8]) -> Option<String> {
+    let be_u32 = |at: usize| -> Option<u32> {
+        icc.get(at..at + 4)
+            .map(|b| u32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    };
+
+    // 128-byte header, then the tag table
+    let tag_count = be_u32(128)? as usize;
+    let mut desc = None;
+    for i in 0..tag_count.min(256) {
+        let entry = 132 + i * 12;
+        if icc.get(entry..entry + 4)? == b"desc" {
+            desc = Some((be_u32(entry + 4)? as usize, be_u32(entry + 8)? as usize));
+            break;
+        }
+    }
+    let (offset, size) = desc?;
+    let tag = icc.get(offset..offset.checked_add(size)?)?;
+
+    match tag.get(0..4)? {
+        // ICC v2 textDescriptionType: ASCII count followed by the string
+        b"desc" => {
+            let len = u32::from_be_bytes(tag.get(8..12)?.try_into().ok()?) as usize;
+            let text = tag.get(12..12 + len)?;
+            let text = String::from_utf8_lossy(text);
+            Some(text.trim_end_matches('\0').trim().to_string())
+        }
+        // ICC v4 multiLocalizedUnicodeType: use the first record
+        b"mluc" => {
+            let len = u32::from_be_bytes(tag.get(20..24)?.try_into().ok()?) as usize;
+            let start = u32::from_be_bytes(tag.get(24..28)?.try_into().ok()?) as usize;
+            let units: Vec<u16> = tag
+                .get(start..start + len)?
+                .chunks_exact(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]))
+                .collect();
+            Some(
+                String::from_utf16_lossy(&units)
+                    .trim_end_matches('\0')
+                    .trim()
+                    .to_string(),
+            )
+        }
+        _ => None,
+    }
+    .filter(|name| !name.is_empty())
+}
@@ -1,9 +1,24 @@
+pub mod adjust;
+pub mod animation;
+pub mod auto;
+pub mod backup;
+pub mod cache;
+pub mod compare;
 pub mod compress;
 pub mod config;
+pub mod format;
+pub mod inspect;
+pub mod jpeg_decode;
 pub mod metadata;
+pub mod output;
 pub mod pipeline;
 pub mod resize;
+pub mod scheduler;
+pub mod svg;
+pub mod thumbnail;
+pub mod watermark;
 
+pub use cache::*;
 pub use compress::*;
 pub use config::*;
 pub use pipeline::*;
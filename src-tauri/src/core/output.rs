@@ -0,0 +1,163 @@
+use crate::core::backup::in_place_config;
+use crate::core::config::{FileConflictMode, OutputFormat, ProcessConfig, ProcessResult};
+use crate::core::format::{canonical_extension, extension_matches};
+use crate::error::{AppError, AppResult, ResultExt};
+use crate::utils::get_file_extension;
+use std::path::{Path, PathBuf};
+
+/// File extension written for an output format
+pub fn output_extension(format: &OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Png => "png",
+        OutputFormat::Jpeg => "jpg",
+        OutputFormat::WebP => "webp",
+        OutputFormat::Gif => "gif",
+        // Placeholder, replaced once the pipeline has picked a format
+        OutputFormat::Auto => "auto",
+    }
+}
+
+/// MIME type of an output format, for showing encoded bytes in the webview
+pub fn output_mime_type(format: &OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Png => "image/png",
+        OutputFormat::Jpeg => "image/jpeg",
+        OutputFormat::WebP => "image/webp",
+        OutputFormat::Gif => "image/gif",
+        OutputFormat::Auto => "application/octet-stream",
+    }
+}
+
+/// `path` with the extension of the format `data` holds, or unchanged when
+/// its extension already names that format or the data isn't recognized
+pub fn detected_extension(path: PathBuf, data: &[u8]) -> PathBuf {
+    match image::guess_format(data) {
+        Ok(format) if !extension_matches(get_file_extension(&path).as_deref(), format) => {
+            path.with_extension(canonical_extension(format))
+        }
+        _ => path,
+    }
+}
+
+/// The config for processing one input, with `output_path` set to the file
+/// it's written to. Returns `None` when the conflict mode skips the file.
+pub fn file_config(input: &Path, config: &ProcessConfig) -> AppResult<Option<ProcessConfig>> {
+    if config.in_place.is_some() {
+        return in_place_config(input, config).map(Some);
+    }
+
+    let output_filename = format!(
+        "{}_optimized.{}",
+        input
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("output"),
+        output_extension(&config.output_format)
+    );
+
+    // Mirror the input's folder below base_path when preserving structure
+    let relative_parent = config
+        .base_path
+        .as_ref()
+        .filter(|_| config.preserve_folder_structure)
+        .and_then(|base_path| input.strip_prefix(base_path).ok())
+        .and_then(Path::parent);
+    let base_output_path = match relative_parent {
+        Some(parent) => config.output_path.join(parent).join(output_filename),
+        None => config.output_path.join(output_filename),
+    };
+
+    // Resolve conflicts; with Auto the extension isn't known yet, so the
+    // pipeline resolves them once it has picked a format
+    let output_path = if config.output_format == OutputFormat::Auto {
+        base_output_path
+    } else {
+        match resolve_output_path(base_output_path, &config.file_conflict_mode)? {
+            Some(path) => path,
+            None => return Ok(None),
+        }
+    };
+
+    if let Some(parent) = output_path.parent() {
+        std::fs::create_dir_all(parent).with_path(parent)?;
+    }
+
+    let mut file_config = config.clone();
+    file_config.output_path = output_path;
+    Ok(Some(file_config))
+}
+
+/// Resolves the output path based on the conflict mode.
+/// Returns `None` when the file should be skipped.
+pub fn resolve_output_path(
+    base_path: PathBuf,
+    conflict_mode: &FileConflictMode,
+) -> AppResult<Option<PathBuf>> {
+    match conflict_mode {
+        FileConflictMode::Overwrite => Ok(Some(base_path)),
+        FileConflictMode::Skip => {
+            if base_path.exists() {
+                Ok(None) // Skip this file
+            } else {
+                Ok(Some(base_path))
+            }
+        }
+        FileConflictMode::Rename => {
+            if !base_path.exists() {
+                return Ok(Some(base_path));
+            }
+
+            let invalid = |what: &str| {
+                AppError::InvalidConfig(format!("Invalid {}", what)).with_path(&base_path)
+            };
+            let stem = base_path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .ok_or_else(|| invalid("filename"))?;
+            let ext = base_path
+                .extension()
+                .and_then(|s| s.to_str())
+                .ok_or_else(|| invalid("extension"))?;
+            let parent = base_path
+                .parent()
+                .ok_or_else(|| invalid("parent directory"))?;
+
+            let mut counter = 1;
+            loop {
+                let new_path = parent.join(format!(
+                    "{}_({}){}",
+                    stem,
+                    counter,
+                    if ext.is_empty() {
+                        String::new()
+                    } else {
+                        format!(".{}", ext)
+                    }
+                ));
+                if !new_path.exists() {
+                    return Ok(Some(new_path));
+                }
+                counter += 1;
+                if counter > 1000 {
+                    return Err(invalid("output path: too many conflicting files"));
+                }
+            }
+        }
+    }
+}
+
+/// Result reported for inputs skipped because their output already exists
+pub fn skipped_result() -> ProcessResult {
+    ProcessResult {
+        success: true,
+        original_size: 0,
+        new_size: 0,
+        compression_ratio: 0.0,
+        output_path: String::from("Skipped (file exists)"),
+        error: None,
+        cached: false,
+        unchanged: false,
+        warnings: Vec::new(),
+        format_choice: None,
+    }
+}
@@ -1,85 +1,546 @@
-use crate::core::compress::{compress_jpeg, compress_png, compress_webp};
-use crate::core::config::{OutputFormat, ProcessConfig, ProcessResult, ProgressEvent};
-use crate::core::resize::resize_image;
-use crate::error::AppResult;
-use image::DynamicImage;
+use crate::core::adjust::{apply_adjustments, flatten_alpha, has_transparency, is_identity};
+use crate::core::animation::{decode_animation, Animation};
+use crate::core::auto::{animation_candidates, candidates, classify};
+use crate::core::backup::BackupRun;
+use crate::core::compress::{
+    compress_apng, compress_gif, compress_gif_animated, compress_jpeg, compress_png, compress_webp,
+    compress_webp_animated, encode_webp_lossy, optimize_jpeg_lossless,
+};
+use crate::core::config::{
+    DecodeLimits, FormatChoice, JpegTransform, OutputFormat, ProcessConfig, ProcessResult,
+    ProgressEvent, ResizeConfig, ResizeMode, TransparencyPolicy,
+};
+use crate::core::format::{
+    check_limits, detect_format, header_reader, is_svg_document, open_image, open_image_scaled,
+};
+use crate::core::metadata::read_embedded_thumbnail;
+use crate::core::output::{
+    detected_extension, output_extension, resolve_output_path, skipped_result,
+};
+use crate::core::resize::{resize_image, target_dimensions};
+use crate::core::scheduler::spawn_blocking;
+use crate::core::svg::render_svg_resized;
+use crate::core::watermark::Watermark;
+use crate::error::{AppError, AppResult, ResultExt};
+use crate::utils::{format_file_size, parse_hex_color, write_atomic};
+use image::{DynamicImage, ImageFormat};
 use std::path::Path;
 use tokio::sync::mpsc;
 
+// JPEG and WebP quality of preview images
+const PREVIEW_QUALITY: u8 = 85;
+
+// Embedded thumbnails are rounded to whole pixels, so their aspect ratio
+// only roughly matches the image's
+const THUMBNAIL_ASPECT_TOLERANCE: f64 = 0.02;
+
+/// An encoded output that hasn't been written anywhere yet
+pub struct EncodedImage {
+    pub data: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    /// Format picked for `OutputFormat::Auto` and why
+    pub format_choice: Option<FormatChoice>,
+    pub warnings: Vec<String>,
+}
+
 pub async fn process_image(
     input_path: &Path,
     config: ProcessConfig,
     progress_tx: Option<mpsc::Sender<ProgressEvent>>,
 ) -> AppResult<ProcessResult> {
-    let original_size = std::fs::metadata(input_path)?.len();
+    let original_size = std::fs::metadata(input_path).with_path(input_path)?.len();
 
-    // Send loading event
-    if let Some(ref tx) = progress_tx {
-        let _ = tx.send(ProgressEvent::Loading).await;
-    }
-
-    // 1. Load image
-    let img = image::open(input_path)?;
+    let encoded = encode_image(input_path, &config, progress_tx.as_ref()).await?;
 
-    // 2. Resize (if needed)
+    // 4. Write to file
     if let Some(ref tx) = progress_tx {
-        let _ = tx.send(ProgressEvent::Resizing).await;
+        let _ = tx.send(ProgressEvent::WritingMetadata).await;
     }
-    let img = resize_image(&img, &config.resize)?;
 
-    // 3. Compress based on output format
+    let result = spawn_blocking(move || write_encoded(encoded, &config, original_size)).await?;
+
+    // Send done event
     if let Some(ref tx) = progress_tx {
-        let _ = tx.send(ProgressEvent::Compressing).await;
+        let _ = tx.send(ProgressEvent::Done).await;
     }
 
-    let output_data = match config.output_format {
-        OutputFormat::Png => compress_png(&img, &config.png_options)?,
-        OutputFormat::Jpeg => compress_jpeg(&img, &config.jpeg_options)?,
-        OutputFormat::WebP => compress_webp(&img, &config.webp_options)?,
-    };
+    Ok(result)
+}
 
-    // 4. Write to file
-    if let Some(ref tx) = progress_tx {
-        let _ = tx.send(ProgressEvent::WritingMetadata).await;
-    }
+/// Writes an encoded image to `config.output_path`, or next to it with the
+/// extension of the format Auto picked
+pub fn write_encoded(
+    encoded: EncodedImage,
+    config: &ProcessConfig,
+    original_size: u64,
+) -> AppResult<ProcessResult> {
+    let EncodedImage {
+        data: output_data,
+        format_choice,
+        warnings,
+        ..
+    } = encoded;
 
-    std::fs::write(&config.output_path, &output_data)?;
+    // The extension of Auto outputs is only known now
+    let output_path = match &format_choice {
+        Some(choice) => {
+            let path = config
+                .output_path
+                .with_extension(output_extension(&choice.format));
+            match resolve_output_path(path, &config.file_conflict_mode)? {
+                Some(path) => path,
+                None => return Ok(skipped_result()),
+            }
+        }
+        None => config.output_path.clone(),
+    };
 
-    let new_size = output_data.len() as u64;
-    let compression_ratio = if original_size > 0 {
-        if new_size <= original_size {
-            ((original_size - new_size) as f32 / original_size as f32) * 100.0
+    // In-place runs write back to the input, which keeps its name
+    let output_path = if config.fix_output_extension && config.in_place.is_none() {
+        let fixed = detected_extension(output_path.clone(), &output_data);
+        if fixed == output_path {
+            output_path
         } else {
-            // Negative compression (file got bigger)
-            -((new_size - original_size) as f32 / original_size as f32) * 100.0
+            match resolve_output_path(fixed, &config.file_conflict_mode)? {
+                Some(path) => path,
+                None => return Ok(skipped_result()),
+            }
         }
     } else {
-        0.0
+        output_path
     };
 
-    // Send done event
-    if let Some(ref tx) = progress_tx {
-        let _ = tx.send(ProgressEvent::Done).await;
+    let new_size = output_data.len() as u64;
+
+    // Never leave a truncated file at the final path
+    match &config.in_place {
+        // Replacing an original with a file at least as large only costs a
+        // backup, so it's left alone and kept out of the run
+        Some(_) if new_size >= original_size => {
+            return Ok(ProcessResult {
+                success: true,
+                original_size,
+                new_size: original_size,
+                compression_ratio: 0.0,
+                output_path: output_path.to_string_lossy().to_string(),
+                error: None,
+                cached: false,
+                unchanged: true,
+                warnings,
+                format_choice,
+            });
+        }
+        Some(in_place) => {
+            BackupRun::open(&in_place.backup_dir)?.replace(&output_path, &output_data)?
+        }
+        None => write_atomic(&output_path, &output_data)?,
     }
 
     Ok(ProcessResult {
         success: true,
         original_size,
         new_size,
-        compression_ratio,
-        output_path: config.output_path.to_string_lossy().to_string(),
+        compression_ratio: compression_ratio(original_size, new_size),
+        output_path: output_path.to_string_lossy().to_string(),
         error: None,
+        cached: false,
+        unchanged: false,
+        warnings,
+        format_choice,
     })
 }
 
+/// Decodes, resizes, adjusts and compresses an image entirely in memory,
+/// on the blocking pool
+pub async fn encode_image(
+    input_path: &Path,
+    config: &ProcessConfig,
+    progress_tx: Option<&mpsc::Sender<ProgressEvent>>,
+) -> AppResult<EncodedImage> {
+    let (input_path, config) = (input_path.to_path_buf(), config.clone());
+    let progress_tx = progress_tx.cloned();
+    spawn_blocking(move || {
+        encode_image_blocking(&input_path, &config, |event| {
+            // Progress is advisory, a full channel just misses an update
+            if let Some(tx) = &progress_tx {
+                let _ = tx.try_send(event);
+            }
+        })
+    })
+    .await
+}
+
+/// The work of `encode_image`, for callers already on a blocking thread.
+/// `progress` is called as each step starts.
+pub fn encode_image_blocking(
+    input_path: &Path,
+    config: &ProcessConfig,
+    progress: impl Fn(ProgressEvent),
+) -> AppResult<EncodedImage> {
+    progress(ProgressEvent::Loading);
+
+    // 1. Load image, keeping every frame when the output can be animated
+    let animation = if supports_animation(&config.output_format) {
+        decode_animation(input_path, &config.limits)?
+    } else {
+        None
+    };
+
+    // Load the watermark once, animations apply it to every frame
+    let watermark = config.watermark.as_ref().map(Watermark::load).transpose()?;
+    let finish = |img: DynamicImage| {
+        let img = apply_adjustments(img, &config.adjustments);
+        match &watermark {
+            Some(watermark) => watermark.apply(&img),
+            None => Ok(img),
+        }
+    };
+
+    // Outputs without an alpha channel get composited onto the matte
+    let matte = if drops_alpha(config) {
+        Some(parse_matte(&config.matte.color)?)
+    } else {
+        None
+    };
+    let mut lost_transparency = false;
+    let lossless_jpeg = lossless_jpeg_applies(input_path, config)?;
+
+    let (output_data, format_choice) = match animation {
+        Some(animation) => {
+            // 2. Resize, adjust and watermark every frame
+            progress(ProgressEvent::Resizing);
+            let animation = animation.map_frames(|frame| {
+                if config.resize.enabled {
+                    finish(resize_image(&frame, &config.resize)?)
+                } else {
+                    finish(frame)
+                }
+            })?;
+            let animation = animation
+                .map_frames(|frame| remove_alpha(frame, matte, config, &mut lost_transparency))
+                .with_path(input_path)?;
+
+            // 3. Compress as an animation
+            progress(ProgressEvent::Compressing);
+            match &config.output_format {
+                OutputFormat::Auto => {
+                    let (formats, profile) = animation_candidates();
+                    encode_smallest(&formats, profile, |format| {
+                        compress_animation(&animation, format, config)
+                    })?
+                }
+                format => (compress_animation(&animation, format, config)?, None),
+            }
+        }
+        None if lossless_jpeg => {
+            // 2-3. Rewrite the DCT coefficients without decoding pixels
+            progress(ProgressEvent::Compressing);
+            // libjpeg allocates the coefficients for the whole image
+            let (width, height) = header_reader(input_path)?
+                .into_dimensions()
+                .with_path(input_path)?;
+            check_limits(width, height, &config.limits).with_path(input_path)?;
+
+            let data = std::fs::read(input_path).with_path(input_path)?;
+            let output = optimize_jpeg_lossless(&data, &config.jpeg_options, &config.metadata_mode)
+                .with_path(input_path)?;
+            (output, None)
+        }
+        None => {
+            // 2. Resize (if needed); SVGs are rendered at the target size
+            let img = if is_svg_document(input_path)? {
+                progress(ProgressEvent::Resizing);
+                render_svg_resized(
+                    input_path,
+                    &config.resize,
+                    &config.svg_options,
+                    &config.limits,
+                )?
+            } else if config.resize.enabled {
+                // Shrinking JPEGs are decoded at a reduced scale, then resized
+                // precisely to the size their full dimensions call for
+                let (img, (width, height)) =
+                    open_image_scaled(input_path, &config.limits, |width, height| {
+                        target_dimensions(width, height, &config.resize.mode)
+                    })?;
+
+                progress(ProgressEvent::Resizing);
+                let (target_width, target_height) =
+                    target_dimensions(width, height, &config.resize.mode);
+                resize_image(
+                    &img,
+                    &ResizeConfig {
+                        enabled: true,
+                        mode: ResizeMode::Exact {
+                            width: target_width,
+                            height: target_height,
+                        },
+                        algorithm: config.resize.algorithm.clone(),
+                    },
+                )?
+            } else {
+                let img = open_image(input_path, &config.limits)?;
+
+                progress(ProgressEvent::Resizing);
+                img
+            };
+            let img = if config.output_format == OutputFormat::Jpeg {
+                apply_transform(img, config.jpeg_options.transform)
+            } else {
+                img
+            };
+            let img = finish(img)?;
+            let img =
+                remove_alpha(img, matte, config, &mut lost_transparency).with_path(input_path)?;
+
+            // 3. Compress based on output format
+            progress(ProgressEvent::Compressing);
+
+            match &config.output_format {
+                OutputFormat::Auto => {
+                    let (formats, profile) = candidates(&classify(&img));
+                    encode_smallest(&formats, profile, |format| {
+                        compress_still(&img, format, config)
+                    })?
+                }
+                format => (compress_still(&img, format, config)?, None),
+            }
+        }
+    };
+
+    let mut warnings = Vec::new();
+    if lost_transparency && config.matte.on_transparency == TransparencyPolicy::Warn {
+        warnings.push(format!(
+            "Transparent areas were filled with {} because {} output has no alpha channel",
+            config.matte.color,
+            format_label(&config.output_format)
+        ));
+    }
+
+    let (width, height) = output_dimensions(&output_data)?;
+
+    Ok(EncodedImage {
+        data: output_data,
+        width,
+        height,
+        format_choice,
+        warnings,
+    })
+}
+
+// Reads the size back from the encoded header, which also covers lossless
+// JPEG transforms that never decode pixels
+fn output_dimensions(data: &[u8]) -> AppResult<(u32, u32)> {
+    Ok(image::ImageReader::new(std::io::Cursor::new(data))
+        .with_guessed_format()?
+        .into_dimensions()?)
+}
+
+// JPEG to JPEG jobs that leave the pixels alone can skip decoding entirely
+fn lossless_jpeg_applies(input_path: &Path, config: &ProcessConfig) -> AppResult<bool> {
+    if config.output_format != OutputFormat::Jpeg
+        || !config.jpeg_options.lossless
+        || config.resize.enabled
+        || config.watermark.is_some()
+        || !is_identity(&config.adjustments)
+    {
+        return Ok(false);
+    }
+    Ok(detect_format(input_path)? == Some(ImageFormat::Jpeg))
+}
+
+// The pixel equivalent of the lossless JPEG transforms
+fn apply_transform(img: DynamicImage, transform: JpegTransform) -> DynamicImage {
+    match transform {
+        JpegTransform::None => img,
+        JpegTransform::Rotate90 => img.rotate90(),
+        JpegTransform::Rotate180 => img.rotate180(),
+        JpegTransform::Rotate270 => img.rotate270(),
+        JpegTransform::FlipHorizontal => img.fliph(),
+        JpegTransform::FlipVertical => img.flipv(),
+    }
+}
+
+// JPEG has no alpha and GIF can be told to drop it
+fn drops_alpha(config: &ProcessConfig) -> bool {
+    match config.output_format {
+        OutputFormat::Jpeg => true,
+        OutputFormat::Gif => !config.gif_options.preserve_transparency,
+        // Auto only considers JPEG for opaque images
+        OutputFormat::Png | OutputFormat::WebP | OutputFormat::Auto => false,
+    }
+}
+
+fn parse_matte(color: &str) -> AppResult<[u8; 3]> {
+    let [r, g, b, _] = parse_hex_color(color)
+        .ok_or_else(|| AppError::InvalidConfig(format!("Invalid matte color: {}", color)))?;
+    Ok([r, g, b])
+}
+
+// Flattens transparent pixels onto the matte, or refuses to when configured
+fn remove_alpha(
+    img: DynamicImage,
+    matte: Option<[u8; 3]>,
+    config: &ProcessConfig,
+    lost_transparency: &mut bool,
+) -> AppResult<DynamicImage> {
+    let Some(matte) = matte else {
+        return Ok(img);
+    };
+    if !has_transparency(&img) {
+        return Ok(flatten_alpha(img, matte));
+    }
+
+    if config.matte.on_transparency == TransparencyPolicy::Refuse {
+        return Err(AppError::UnsupportedFormat(format!(
+            "Image has transparency, which {} output can't keep",
+            format_label(&config.output_format)
+        )));
+    }
+
+    *lost_transparency = true;
+    Ok(flatten_alpha(img, matte))
+}
+
+fn format_label(format: &OutputFormat) -> &'static str {
+    match format {
+        OutputFormat::Png => "PNG",
+        OutputFormat::Jpeg => "JPEG",
+        OutputFormat::WebP => "WebP",
+        OutputFormat::Gif => "GIF",
+        OutputFormat::Auto => "Auto",
+    }
+}
+
+/// Output formats that keep every frame of an animated input
+pub fn supports_animation(format: &OutputFormat) -> bool {
+    matches!(
+        format,
+        OutputFormat::Png | OutputFormat::WebP | OutputFormat::Gif | OutputFormat::Auto
+    )
+}
+
+fn compress_still(
+    img: &DynamicImage,
+    format: &OutputFormat,
+    config: &ProcessConfig,
+) -> AppResult<Vec<u8>> {
+    match format {
+        OutputFormat::Png => compress_png(img, &config.png_options),
+        OutputFormat::Jpeg => compress_jpeg(img, &config.jpeg_options),
+        OutputFormat::WebP => compress_webp(img, &config.webp_options),
+        OutputFormat::Gif => compress_gif(img, &config.gif_options),
+        OutputFormat::Auto => Err(AppError::InvalidConfig(
+            "Auto must be resolved to a concrete format".to_string(),
+        )),
+    }
+}
+
+fn compress_animation(
+    animation: &Animation,
+    format: &OutputFormat,
+    config: &ProcessConfig,
+) -> AppResult<Vec<u8>> {
+    match format {
+        OutputFormat::Png => compress_apng(animation, &config.png_options),
+        OutputFormat::WebP => compress_webp_animated(animation, &config.webp_options),
+        OutputFormat::Gif => compress_gif_animated(animation, &config.gif_options),
+        OutputFormat::Jpeg => Err(AppError::UnsupportedFormat(
+            "JPEG cannot store animations".to_string(),
+        )),
+        OutputFormat::Auto => Err(AppError::InvalidConfig(
+            "Auto must be resolved to a concrete format".to_string(),
+        )),
+    }
+}
+
+// Encodes every candidate and keeps the smallest. Each one uses its own
+// configured quality, so the size comparison is at the requested quality.
+fn encode_smallest<F>(
+    formats: &[OutputFormat],
+    profile: &str,
+    mut encode: F,
+) -> AppResult<(Vec<u8>, Option<FormatChoice>)>
+where
+    F: FnMut(&OutputFormat) -> AppResult<Vec<u8>>,
+{
+    let mut best: Option<(OutputFormat, Vec<u8>)> = None;
+    let mut sizes = Vec::new();
+    let mut first_error = None;
+
+    for format in formats {
+        match encode(format) {
+            Ok(data) => {
+                sizes.push(format!(
+                    "{} {}",
+                    format_label(format),
+                    format_file_size(data.len() as u64)
+                ));
+                if best.as_ref().is_none_or(|(_, b)| data.len() < b.len()) {
+                    best = Some((format.clone(), data));
+                }
+            }
+            Err(e) => {
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    let Some((format, data)) = best else {
+        return Err(first_error.unwrap_or_else(|| {
+            AppError::InvalidConfig("No candidate output formats".to_string())
+        }));
+    };
+
+    let reason = format!(
+        "{}; {} was smallest ({})",
+        profile,
+        format_label(&format),
+        sizes.join(", ")
+    );
+    Ok((data, Some(FormatChoice { format, reason })))
+}
+
+pub fn compression_ratio(original_size: u64, new_size: u64) -> f32 {
+    if original_size > 0 {
+        if new_size <= original_size {
+            ((original_size - new_size) as f32 / original_size as f32) * 100.0
+        } else {
+            // Negative compression (file got bigger)
+            -((new_size - original_size) as f32 / original_size as f32) * 100.0
+        }
+    } else {
+        0.0
+    }
+}
+
 pub fn generate_preview(img: &DynamicImage, max_size: u32) -> AppResult<String> {
-    // Resize for preview (max 800px)
-    let width = img.width();
-    let height = img.height();
-    let scale = (max_size as f32 / width.max(height) as f32).min(1.0);
+    // Convert to base64
+    Ok(base64::Engine::encode(
+        &base64::engine::general_purpose::STANDARD,
+        encode_preview(img, max_size)?,
+    ))
+}
 
-    let preview_width = (width as f32 * scale) as u32;
-    let preview_height = (height as f32 * scale) as u32;
+/// Opens an image for a preview of at most `max_size` pixels and encodes it.
+/// An embedded EXIF thumbnail is used when it's big enough; otherwise large
+/// JPEGs only decode at the scale the preview needs.
+pub fn load_preview(path: &Path, max_size: u32, limits: &DecodeLimits) -> AppResult<Vec<u8>> {
+    if let Some(thumbnail) = embedded_preview(path, max_size) {
+        return encode_preview(&thumbnail, max_size);
+    }
+
+    let (img, _) = open_image_scaled(path, limits, |width, height| {
+        preview_dimensions(width, height, max_size)
+    })?;
+    encode_preview(&img, max_size)
+}
+
+/// Scales the image to fit `max_size` and encodes it as a JPEG, or as a
+/// lossy WebP when it has transparency so the checkerboard still shows
+pub fn encode_preview(img: &DynamicImage, max_size: u32) -> AppResult<Vec<u8>> {
+    let (preview_width, preview_height) = preview_dimensions(img.width(), img.height(), max_size);
 
     let preview = img.resize_exact(
         preview_width,
@@ -87,10 +548,14 @@ pub fn generate_preview(img: &DynamicImage, max_size: u32) -> AppResult<String>
         image::imageops::FilterType::Lanczos3,
     );
 
-    // Encode to JPEG for preview
+    if has_transparency(&preview) {
+        return encode_webp_lossy(&preview.to_rgba8(), PREVIEW_QUALITY as f32);
+    }
+
     let mut jpeg_data = Vec::new();
     {
-        let mut encoder = image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg_data, 85);
+        let mut encoder =
+            image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg_data, PREVIEW_QUALITY);
         let rgb = preview.to_rgb8();
         encoder.encode(
             rgb.as_raw(),
@@ -100,9 +565,37 @@ pub fn generate_preview(img: &DynamicImage, max_size: u32) -> AppResult<String>
         )?;
     }
 
-    // Convert to base64
-    Ok(base64::Engine::encode(
-        &base64::engine::general_purpose::STANDARD,
-        jpeg_data,
-    ))
+    Ok(jpeg_data)
+}
+
+// Cameras store a small JPEG in the EXIF data. It's only used when it
+// covers the preview and has the image's aspect ratio, since some are
+// letterboxed to a fixed size.
+fn embedded_preview(path: &Path, max_size: u32) -> Option<DynamicImage> {
+    let (width, height) = header_reader(path).ok()?.into_dimensions().ok()?;
+    let data = read_embedded_thumbnail(path)?;
+
+    let (thumb_width, thumb_height) = image::ImageReader::new(std::io::Cursor::new(&data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()?;
+    let (needed_width, needed_height) = preview_dimensions(width, height, max_size);
+    let aspect = |w: u32, h: u32| f64::from(w) / f64::from(h.max(1));
+    let same_framing = (aspect(thumb_width, thumb_height) / aspect(width, height) - 1.0).abs()
+        < THUMBNAIL_ASPECT_TOLERANCE;
+    if thumb_width < needed_width || thumb_height < needed_height || !same_framing {
+        return None;
+    }
+
+    image::load_from_memory(&data).ok()
+}
+
+// Fits within `max_size` on the longer side, never enlarging
+fn preview_dimensions(width: u32, height: u32, max_size: u32) -> (u32, u32) {
+    let scale = (max_size as f32 / width.max(height) as f32).min(1.0);
+    (
+        (width as f32 * scale) as u32,
+        (height as f32 * scale) as u32,
+    )
 }
@@ -11,37 +11,7 @@ pub fn resize_image(img: &DynamicImage, config: &ResizeConfig) -> AppResult<Dyna
     let orig_width = img.width();
     let orig_height = img.height();
 
-    // Calculate target dimensions
-    let (target_width, target_height) = match &config.mode {
-        ResizeMode::FixedWidth { width } => {
-            let aspect_ratio = orig_height as f32 / orig_width as f32;
-            (*width, (*width as f32 * aspect_ratio) as u32)
-        }
-        ResizeMode::FixedHeight { height } => {
-            let aspect_ratio = orig_width as f32 / orig_height as f32;
-            ((*height as f32 * aspect_ratio) as u32, *height)
-        }
-        ResizeMode::Exact { width, height } => (*width, *height),
-        ResizeMode::Percentage { scale } => {
-            let w = (orig_width as f32 * scale / 100.0) as u32;
-            let h = (orig_height as f32 * scale / 100.0) as u32;
-            (w, h)
-        }
-        ResizeMode::FitBox {
-            max_width,
-            max_height,
-        } => {
-            let width_ratio = *max_width as f32 / orig_width as f32;
-            let height_ratio = *max_height as f32 / orig_height as f32;
-            let ratio = width_ratio.min(height_ratio);
-
-            (
-                (orig_width as f32 * ratio) as u32,
-                (orig_height as f32 * ratio) as u32,
-            )
-        }
-        ResizeMode::FillBox { width, height } => (*width, *height),
-    };
+    let (target_width, target_height) = target_dimensions(orig_width, orig_height, &config.mode);
 
     // Skip if dimensions are the same
     if target_width == orig_width && target_height == orig_height {
@@ -91,3 +61,37 @@ pub fn resize_image(img: &DynamicImage, config: &ResizeConfig) -> AppResult<Dyna
 
     Ok(DynamicImage::ImageRgba8(rgba_image))
 }
+
+/// Output dimensions a resize mode produces for an image of the given size
+pub fn target_dimensions(orig_width: u32, orig_height: u32, mode: &ResizeMode) -> (u32, u32) {
+    match mode {
+        ResizeMode::FixedWidth { width } => {
+            let aspect_ratio = orig_height as f32 / orig_width as f32;
+            (*width, (*width as f32 * aspect_ratio) as u32)
+        }
+        ResizeMode::FixedHeight { height } => {
+            let aspect_ratio = orig_width as f32 / orig_height as f32;
+            ((*height as f32 * aspect_ratio) as u32, *height)
+        }
+        ResizeMode::Exact { width, height } => (*width, *height),
+        ResizeMode::Percentage { scale } => {
+            let w = (orig_width as f32 * scale / 100.0) as u32;
+            let h = (orig_height as f32 * scale / 100.0) as u32;
+            (w, h)
+        }
+        ResizeMode::FitBox {
+            max_width,
+            max_height,
+        } => {
+            let width_ratio = *max_width as f32 / orig_width as f32;
+            let height_ratio = *max_height as f32 / orig_height as f32;
+            let ratio = width_ratio.min(height_ratio);
+
+            (
+                (orig_width as f32 * ratio) as u32,
+                (orig_height as f32 * ratio) as u32,
+            )
+        }
+        ResizeMode::FillBox { width, height } => (*width, *height),
+    }
+}
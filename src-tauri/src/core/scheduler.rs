@@ -0,0 +1,112 @@
+use crate::core::config::BatchOptions;
+use crate::core::format::header_reader;
+use crate::core::inspect::frame_count;
+use crate::error::{AppError, AppResult};
+use std::path::Path;
+use tokio::sync::Semaphore;
+
+const MIB: u64 = 1024 * 1024;
+
+// Peak memory per decoded pixel: RGBA pixels plus the copies made while
+// converting, resizing and encoding
+const BYTES_PER_PIXEL: u64 = 4 * 3;
+
+// Used when the header doesn't give dimensions, e.g. for SVG
+const UNKNOWN_ESTIMATE: u64 = 64 * MIB;
+
+// Inputs read at once for hashing and header probes
+const IO_CONCURRENCY: usize = 8;
+
+/// Limits a batch's concurrent work three ways: file reads and writes,
+/// CPU-bound encodes, and the estimated memory of the images being encoded.
+///
+/// Memory is reserved in MiB from a fair semaphore, so a large image waits
+/// for enough room instead of being overtaken by smaller ones forever.
+pub struct BatchScheduler {
+    io: Semaphore,
+    workers: Semaphore,
+    memory: Semaphore,
+    memory_limit_mb: u32,
+}
+
+impl BatchScheduler {
+    pub fn new(options: &BatchOptions) -> Self {
+        let workers = worker_count(options.workers);
+        let memory_limit_mb = options.memory_limit_mb.max(1);
+
+        Self {
+            io: Semaphore::new(IO_CONCURRENCY),
+            workers: Semaphore::new(workers),
+            memory: Semaphore::new(memory_limit_mb as usize),
+            memory_limit_mb,
+        }
+    }
+
+    /// Runs blocking IO on the blocking pool, at most `IO_CONCURRENCY` at once
+    pub async fn run_io<T, F>(&self, job: F) -> AppResult<T>
+    where
+        F: FnOnce() -> AppResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        let _permit = self.io.acquire().await.map_err(closed)?;
+        spawn_blocking(job).await
+    }
+
+    /// Waits for a free worker and `memory` bytes of the budget, then runs
+    /// the job on the blocking pool. Both are released when it finishes.
+    pub async fn run_cpu<T, F>(&self, memory: u64, job: F) -> AppResult<T>
+    where
+        F: FnOnce() -> AppResult<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        // Too large for the budget: wait until it can have all of it
+        let mb = memory
+            .div_ceil(MIB)
+            .clamp(1, u64::from(self.memory_limit_mb)) as u32;
+        let _memory = self.memory.acquire_many(mb).await.map_err(closed)?;
+        let _worker = self.workers.acquire().await.map_err(closed)?;
+        spawn_blocking(job).await
+    }
+}
+
+/// Resolves a configured worker count, where 0 means one per CPU core
+pub fn worker_count(workers: usize) -> usize {
+    match workers {
+        0 => std::thread::available_parallelism().map_or(4, |n| n.get()),
+        n => n,
+    }
+}
+
+/// Rough peak memory of processing an image, from its header dimensions.
+/// With `keeps_frames`, animations count every frame, since those outputs
+/// decode all of them up front.
+pub fn estimate_memory(path: &Path, keeps_frames: bool) -> u64 {
+    let Ok(reader) = header_reader(path) else {
+        return UNKNOWN_ESTIMATE;
+    };
+    let format = reader.format();
+    let Ok((width, height)) = reader.into_dimensions() else {
+        return UNKNOWN_ESTIMATE;
+    };
+
+    let frames = match format {
+        Some(format) if keeps_frames => frame_count(path, format),
+        _ => 1,
+    };
+    u64::from(width) * u64::from(height) * u64::from(frames) * BYTES_PER_PIXEL
+}
+
+/// Runs a job on the blocking pool, failing if its thread panicked
+pub async fn spawn_blocking<T, F>(job: F) -> AppResult<T>
+where
+    F: FnOnce() -> AppResult<T> + Send + 'static,
+    T: Send + 'static,
+{
+    tokio::task::spawn_blocking(job)
+        .await
+        .map_err(|e| AppError::Processing(format!("Worker failed: {}", e)))?
+}
+
+fn closed(_: tokio::sync::AcquireError) -> AppError {
+    AppError::Processing("Batch scheduler was shut down".to_string())
+}
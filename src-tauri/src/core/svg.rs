@@ -0,0 +1,179 @@
+use crate::core::config::{DecodeLimits, ResizeConfig, ResizeMode, SvgOptions};
+use crate::core::format::check_limits;
+use crate::core::resize::{resize_image, target_dimensions};
+use crate::error::{AppError, AppResult, ResultExt};
+use crate::utils::parse_hex_color;
+use image::{DynamicImage, RgbaImage};
+use resvg::{tiny_skia, usvg};
+use std::io::Read;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+// SVG lengths are in CSS pixels, defined as 1/96 inch
+const CSS_DPI: f32 = 96.0;
+
+// Far enough into the file to get past an XML prolog, comments and a doctype
+const SNIFF_LEN: u64 = 1024;
+
+/// Whether the file is an SVG document (or gzip-compressed `.svgz`)
+pub fn is_svg(path: &Path) -> bool {
+    let Ok(file) = std::fs::File::open(path) else {
+        return false;
+    };
+    let mut header = Vec::new();
+    if file.take(SNIFF_LEN).read_to_end(&mut header).is_err() {
+        return false;
+    }
+
+    if header.starts_with(&[0x1F, 0x8B]) {
+        return crate::utils::get_file_extension(path).as_deref() == Some("svgz");
+    }
+
+    String::from_utf8_lossy(&header).contains("<svg")
+}
+
+/// Intrinsic size in pixels at the configured DPI
+pub fn svg_dimensions(path: &Path, options: &SvgOptions) -> AppResult<(u32, u32)> {
+    let tree = load_tree(path)?;
+    let (width, height) = intrinsic_size(&tree, options);
+    Ok((width.round() as u32, height.round() as u32))
+}
+
+/// Renders an SVG at its intrinsic size
+pub fn render_svg(
+    path: &Path,
+    options: &SvgOptions,
+    limits: &DecodeLimits,
+) -> AppResult<DynamicImage> {
+    let tree = load_tree(path)?;
+    let (width, height) = intrinsic_size(&tree, options);
+    rasterize(
+        &tree,
+        width.round() as u32,
+        height.round() as u32,
+        options,
+        limits,
+    )
+    .with_path(path)
+}
+
+/// Renders an SVG directly at the size the resize settings ask for, so
+/// small icons exported large stay sharp instead of being upscaled
+pub fn render_svg_resized(
+    path: &Path,
+    resize: &ResizeConfig,
+    options: &SvgOptions,
+    limits: &DecodeLimits,
+) -> AppResult<DynamicImage> {
+    if !resize.enabled {
+        return render_svg(path, options, limits);
+    }
+
+    let tree = load_tree(path)?;
+    let (width, height) = intrinsic_size(&tree, options);
+    let (target_width, target_height) =
+        target_dimensions(width.round() as u32, height.round() as u32, &resize.mode);
+
+    // Keep the aspect ratio while covering the target, then let the regular
+    // resize stretch the result for Exact and FillBox
+    let scale = (target_width as f32 / width).max(target_height as f32 / height);
+    let rendered = rasterize(
+        &tree,
+        (width * scale).round() as u32,
+        (height * scale).round() as u32,
+        options,
+        limits,
+    )
+    .with_path(path)?;
+
+    resize_image(
+        &rendered,
+        &ResizeConfig {
+            enabled: true,
+            mode: ResizeMode::Exact {
+                width: target_width,
+                height: target_height,
+            },
+            algorithm: resize.algorithm.clone(),
+        },
+    )
+}
+
+fn load_tree(path: &Path) -> AppResult<usvg::Tree> {
+    let data = std::fs::read(path).with_path(path)?;
+
+    let options = usvg::Options {
+        resources_dir: path.parent().map(Path::to_path_buf),
+        fontdb: system_fonts(),
+        ..usvg::Options::default()
+    };
+
+    usvg::Tree::from_data(&data, &options)
+        .map_err(|e| AppError::Decode(format!("Invalid SVG: {}", e)).with_path(path))
+}
+
+// Loading system fonts takes a while, so it's done once per process
+fn system_fonts() -> Arc<usvg::fontdb::Database> {
+    static FONTS: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+
+    FONTS
+        .get_or_init(|| {
+            let mut db = usvg::fontdb::Database::new();
+            db.load_system_fonts();
+            Arc::new(db)
+        })
+        .clone()
+}
+
+fn intrinsic_size(tree: &usvg::Tree, options: &SvgOptions) -> (f32, f32) {
+    let scale = options.dpi.max(1.0) / CSS_DPI;
+    let size = tree.size();
+    (size.width() * scale, size.height() * scale)
+}
+
+fn rasterize(
+    tree: &usvg::Tree,
+    width: u32,
+    height: u32,
+    options: &SvgOptions,
+    limits: &DecodeLimits,
+) -> AppResult<DynamicImage> {
+    let (width, height) = (width.max(1), height.max(1));
+    // A tiny document can ask for an enormous canvas through its size or DPI
+    check_limits(width, height, limits)?;
+    let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or_else(|| {
+        AppError::Processing(format!("Cannot render SVG at {}x{}", width, height))
+    })?;
+
+    if let Some(background) = &options.background {
+        let [r, g, b, a] = parse_hex_color(background).ok_or_else(|| {
+            AppError::InvalidConfig(format!("Invalid SVG background color: {}", background))
+        })?;
+        pixmap.fill(tiny_skia::Color::from_rgba8(r, g, b, a));
+    }
+
+    let size = tree.size();
+    let transform = tiny_skia::Transform::from_scale(
+        width as f32 / size.width(),
+        height as f32 / size.height(),
+    );
+    resvg::render(tree, transform, &mut pixmap.as_mut());
+
+    pixmap_to_image(&pixmap)
+}
+
+pub fn pixmap_to_image(pixmap: &tiny_skia::Pixmap) -> AppResult<DynamicImage> {
+    // tiny-skia stores premultiplied alpha
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|p| {
+            let c = p.demultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+
+    RgbaImage::from_raw(pixmap.width(), pixmap.height(), pixels)
+        .map(DynamicImage::ImageRgba8)
+        .ok_or_else(|| AppError::Processing("Failed to create image from buffer".to_string()))
+}
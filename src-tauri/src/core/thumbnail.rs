@@ -0,0 +1,187 @@
+use crate::core::config::DecodeLimits;
+use crate::core::pipeline::load_preview;
+use crate::error::{AppResult, ResultExt};
+use crate::utils::write_atomic;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Total size the cache may grow to before the least recently used
+/// thumbnails are evicted
+pub const MAX_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+
+// Eviction goes a little below the limit so it doesn't run on every insert
+const EVICT_TARGET: f64 = 0.9;
+
+// Eviction lists the whole cache, so new thumbnails only trigger it this
+// often. The cache can overshoot its limit by this many thumbnails.
+const EVICT_INTERVAL: u64 = 64;
+
+// Bounds for requested thumbnail sizes
+const MIN_SIZE: u32 = 16;
+const MAX_SIZE: u32 = 2048;
+
+/// Sizes served over the preview protocol. Requests are rounded up to one
+/// of these, so a window resize doesn't fill the cache with near duplicates.
+pub const SIZE_BUCKETS: [u32; 6] = [128, 256, 512, 800, 1600, 2048];
+
+// JPEG or WebP, so entries don't carry a format extension
+const ENTRY_EXTENSION: &str = "thumb";
+
+// Bumped by each scan, so pre-generation for an older scan stops early
+static GENERATION: AtomicU64 = AtomicU64::new(0);
+
+// Thumbnails written since the last eviction, across all cache handles
+static WRITES_SINCE_EVICT: AtomicU64 = AtomicU64::new(0);
+
+/// Preview images keyed by source path, size, modification time and
+/// thumbnail size, so edited files get fresh thumbnails automatically.
+///
+/// Like `ProcessCache`, every entry is its own file; the file's modification
+/// time doubles as its last-used time for eviction.
+pub struct ThumbnailCache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+pub struct Thumbnail {
+    pub data: Vec<u8>,
+    /// See `ThumbnailCache::key`
+    pub key: String,
+}
+
+impl ThumbnailCache {
+    pub fn open(dir: PathBuf, max_bytes: u64) -> AppResult<Self> {
+        fs::create_dir_all(&dir).with_path(&dir)?;
+        Ok(Self { dir, max_bytes })
+    }
+
+    /// Returns the cached thumbnail, generating and storing it on a miss.
+    /// Every `EVICT_INTERVAL` misses also evict old thumbnails.
+    pub fn get_or_create(
+        &self,
+        path: &Path,
+        max_size: u32,
+        limits: &DecodeLimits,
+    ) -> AppResult<Thumbnail> {
+        let key = self.key(path, max_size)?;
+        let entry = self.dir.join(format!("{key}.{ENTRY_EXTENSION}"));
+
+        if let Ok(data) = fs::read(&entry) {
+            // Mark as recently used; failing to only affects eviction order
+            if let Ok(file) = File::options().write(true).open(&entry) {
+                let _ = file.set_modified(SystemTime::now());
+            }
+            return Ok(Thumbnail { data, key });
+        }
+
+        let data = load_preview(path, max_size.clamp(MIN_SIZE, MAX_SIZE), limits)?;
+
+        // Pre-generation and preview requests may write the same entry at once
+        write_atomic(&entry, &data)?;
+
+        if WRITES_SINCE_EVICT.fetch_add(1, Ordering::Relaxed) + 1 >= EVICT_INTERVAL {
+            let _ = self.evict();
+        }
+
+        Ok(Thumbnail { data, key })
+    }
+
+    /// Removes the least recently used thumbnails until the cache fits its limit
+    pub fn evict(&self) -> AppResult<()> {
+        WRITES_SINCE_EVICT.store(0, Ordering::Relaxed);
+        let mut entries = self.entries()?;
+        let mut total: u64 = entries.iter().map(|(_, size, _)| size).sum();
+        if total <= self.max_bytes {
+            return Ok(());
+        }
+
+        let target = (self.max_bytes as f64 * EVICT_TARGET) as u64;
+        entries.sort_by_key(|(_, _, used)| *used);
+        for (path, size, _) in entries {
+            if total <= target {
+                break;
+            }
+            if fs::remove_file(&path).is_ok() {
+                total -= size;
+            }
+        }
+        Ok(())
+    }
+
+    /// Deletes every thumbnail and returns the number of bytes freed
+    pub fn clear(&self) -> AppResult<u64> {
+        let mut freed = 0;
+        for (path, size, _) in self.entries()? {
+            if fs::remove_file(&path).is_ok() {
+                freed += size;
+            }
+        }
+        Ok(freed)
+    }
+
+    /// Generates thumbnails for a scanned folder, one at a time so it doesn't
+    /// compete with processing. Stops when a newer scan starts.
+    pub fn pregenerate(&self, paths: &[PathBuf], max_size: u32) {
+        let generation = GENERATION.fetch_add(1, Ordering::SeqCst) + 1;
+
+        for path in paths {
+            if GENERATION.load(Ordering::SeqCst) != generation {
+                return;
+            }
+            // Unreadable files just get no thumbnail; the preview call will
+            // report the error if the user opens them
+            let _ = self.get_or_create(path, max_size, &DecodeLimits::preview());
+        }
+
+        let _ = self.evict();
+    }
+
+    fn entries(&self) -> AppResult<Vec<(PathBuf, u64, SystemTime)>> {
+        let entries = fs::read_dir(&self.dir)
+            .with_path(&self.dir)?
+            .filter_map(|entry| {
+                let entry = entry.ok()?;
+                // Skip temp files of writes still in progress
+                let path = entry.path();
+                if path.extension()? != ENTRY_EXTENSION {
+                    return None;
+                }
+                let metadata = entry.metadata().ok()?;
+                let used = metadata.modified().unwrap_or(UNIX_EPOCH);
+                metadata.is_file().then_some((path, metadata.len(), used))
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    /// Identifies a thumbnail without reading or generating it. Changes
+    /// whenever the source file does, so it also serves as an HTTP ETag.
+    pub fn key(&self, path: &Path, max_size: u32) -> AppResult<String> {
+        let max_size = max_size.clamp(MIN_SIZE, MAX_SIZE);
+        let metadata = fs::metadata(path).with_path(path)?;
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+
+        let mut key = blake3::Hasher::new();
+        key.update(path.to_string_lossy().as_bytes());
+        key.update(&metadata.len().to_le_bytes());
+        key.update(&modified.to_le_bytes());
+        key.update(&max_size.to_le_bytes());
+
+        Ok(key.finalize().to_hex().to_string())
+    }
+}
+
+/// Rounds a requested size up to the nearest of `SIZE_BUCKETS`
+pub fn bucket_size(size: u32) -> u32 {
+    SIZE_BUCKETS
+        .into_iter()
+        .find(|&bucket| bucket >= size)
+        .unwrap_or(MAX_SIZE)
+}
@@ -0,0 +1,226 @@
+use crate::core::adjust::restore_channels;
+use crate::core::config::{
+    DecodeLimits, ResizeAlgorithm, ResizeConfig, ResizeMode, WatermarkOptions, WatermarkPosition,
+    WatermarkSource,
+};
+use crate::core::format::open_image;
+use crate::core::resize::resize_image;
+use crate::core::svg::pixmap_to_image;
+use crate::error::{AppError, AppResult};
+use crate::utils::parse_hex_color;
+use image::DynamicImage;
+use resvg::{tiny_skia, usvg};
+use std::sync::{Arc, OnceLock};
+
+const FONT_DATA: &[u8] = include_bytes!("../../assets/fonts/DejaVuSans-Bold.ttf");
+const FONT_FAMILY: &str = "DejaVu Sans";
+
+// Text is laid out at this size, then scaled to the requested width
+const LAYOUT_FONT_SIZE: f32 = 100.0;
+
+// Tiles run along this angle, in degrees clockwise from horizontal
+const TILE_ANGLE: f32 = -45.0;
+
+/// A watermark source loaded once and applied to any number of images.
+pub struct Watermark {
+    mark: Mark,
+    options: WatermarkOptions,
+}
+
+enum Mark {
+    Image(DynamicImage),
+    Text(Box<usvg::Tree>),
+}
+
+impl Watermark {
+    pub fn load(options: &WatermarkOptions) -> AppResult<Self> {
+        let mark = match &options.source {
+            WatermarkSource::Image { path } => {
+                Mark::Image(open_image(path, &DecodeLimits::default())?)
+            }
+            WatermarkSource::Text { text, color } => {
+                Mark::Text(Box::new(layout_text(text, color)?))
+            }
+        };
+
+        Ok(Self {
+            mark,
+            options: options.clone(),
+        })
+    }
+
+    /// Composites the watermark over `img`, sized relative to its width
+    pub fn apply(&self, img: &DynamicImage) -> AppResult<DynamicImage> {
+        let (width, height) = (img.width() as f32, img.height() as f32);
+        let mark_width = (width * self.options.scale / 100.0).round().max(1.0) as u32;
+        let mark = self.render(mark_width)?;
+
+        let mut canvas = image_to_pixmap(img)?;
+        let paint = tiny_skia::PixmapPaint {
+            opacity: f32::from(self.options.opacity.min(100)) / 100.0,
+            quality: tiny_skia::FilterQuality::Bicubic,
+            ..tiny_skia::PixmapPaint::default()
+        };
+        let (mark_w, mark_h) = (mark.width() as f32, mark.height() as f32);
+
+        if self.options.tiled {
+            // Lay a grid over the diagonal of the image and rotate it about the
+            // center, so every corner is covered whatever the angle
+            let diagonal = width.hypot(height);
+            let (step_x, step_y) = (mark_w * 1.5, mark_h * 3.0);
+            let (center_x, center_y) = (width / 2.0, height / 2.0);
+            let rotation = tiny_skia::Transform::from_rotate_at(TILE_ANGLE, center_x, center_y);
+
+            let rows = (diagonal / step_y).ceil() as i32;
+            let cols = (diagonal / step_x).ceil() as i32;
+            for row in -rows / 2 - 1..=rows / 2 + 1 {
+                // Stagger alternate rows by half a tile
+                let stagger = if row % 2 == 0 { 0.0 } else { step_x / 2.0 };
+                for col in -cols / 2 - 1..=cols / 2 + 1 {
+                    let x = center_x + col as f32 * step_x + stagger - mark_w / 2.0;
+                    let y = center_y + row as f32 * step_y - mark_h / 2.0;
+                    canvas.draw_pixmap(
+                        0,
+                        0,
+                        mark.as_ref(),
+                        &paint,
+                        rotation.pre_translate(x, y),
+                        None,
+                    );
+                }
+            }
+        } else {
+            let margin = width.min(height) * self.options.margin / 100.0;
+            let (x, y) = match self.options.position {
+                WatermarkPosition::TopLeft => (margin, margin),
+                WatermarkPosition::TopRight => (width - mark_w - margin, margin),
+                WatermarkPosition::BottomLeft => (margin, height - mark_h - margin),
+                WatermarkPosition::BottomRight => {
+                    (width - mark_w - margin, height - mark_h - margin)
+                }
+                WatermarkPosition::Center => ((width - mark_w) / 2.0, (height - mark_h) / 2.0),
+            };
+            canvas.draw_pixmap(
+                x.round() as i32,
+                y.round() as i32,
+                mark.as_ref(),
+                &paint,
+                tiny_skia::Transform::identity(),
+                None,
+            );
+        }
+
+        let watermarked = pixmap_to_image(&canvas)?;
+        Ok(restore_channels(watermarked, img.color().has_alpha()))
+    }
+
+    // Rasterizes the mark at the given width, keeping its aspect ratio
+    fn render(&self, width: u32) -> AppResult<tiny_skia::Pixmap> {
+        match &self.mark {
+            Mark::Image(logo) => {
+                let logo = resize_image(
+                    logo,
+                    &ResizeConfig {
+                        enabled: true,
+                        mode: ResizeMode::FixedWidth { width },
+                        algorithm: ResizeAlgorithm::Lanczos3,
+                    },
+                )?;
+                image_to_pixmap(&logo)
+            }
+            Mark::Text(tree) => {
+                let bounds = tree.root().abs_bounding_box();
+                let scale = width as f32 / bounds.width();
+                let height = (bounds.height() * scale).round().max(1.0) as u32;
+
+                let mut pixmap = tiny_skia::Pixmap::new(width, height).ok_or_else(|| {
+                    AppError::Processing(format!("Cannot render watermark at {}px", width))
+                })?;
+                let transform = tiny_skia::Transform::from_scale(scale, scale)
+                    .pre_translate(-bounds.x(), -bounds.y());
+                resvg::render(tree, transform, &mut pixmap.as_mut());
+
+                Ok(pixmap)
+            }
+        }
+    }
+}
+
+// Lays the text out as vector paths with the bundled font
+fn layout_text(text: &str, color: &str) -> AppResult<usvg::Tree> {
+    if text.trim().is_empty() {
+        return Err(AppError::InvalidConfig(
+            "Watermark text is empty".to_string(),
+        ));
+    }
+
+    let [r, g, b, a] = parse_hex_color(color)
+        .ok_or_else(|| AppError::InvalidConfig(format!("Invalid watermark color: {}", color)))?;
+
+    let svg = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="1" height="1"><text x="0" y="{size}" font-family="{family}" font-size="{size}" font-weight="bold" fill="rgb({r},{g},{b})" fill-opacity="{opacity}" xml:space="preserve">{text}</text></svg>"#,
+        size = LAYOUT_FONT_SIZE,
+        family = FONT_FAMILY,
+        opacity = f32::from(a) / 255.0,
+        text = escape_xml(text),
+    );
+
+    let options = usvg::Options {
+        font_family: FONT_FAMILY.to_string(),
+        fontdb: bundled_font(),
+        ..usvg::Options::default()
+    };
+
+    let tree = usvg::Tree::from_str(&svg, &options)
+        .map_err(|e| AppError::Processing(format!("Failed to lay out watermark: {}", e)))?;
+
+    let bounds = tree.root().abs_bounding_box();
+    if bounds.width() <= 0.0 || bounds.height() <= 0.0 {
+        return Err(AppError::InvalidConfig(
+            "Watermark text has no visible glyphs".to_string(),
+        ));
+    }
+
+    Ok(tree)
+}
+
+fn bundled_font() -> Arc<usvg::fontdb::Database> {
+    static FONT: OnceLock<Arc<usvg::fontdb::Database>> = OnceLock::new();
+
+    FONT.get_or_init(|| {
+        let mut db = usvg::fontdb::Database::new();
+        db.load_font_data(FONT_DATA.to_vec());
+        Arc::new(db)
+    })
+    .clone()
+}
+
+fn escape_xml(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '&' => "&amp;".to_string(),
+            '<' => "&lt;".to_string(),
+            '>' => "&gt;".to_string(),
+            '"' => "&quot;".to_string(),
+            '\'' => "&apos;".to_string(),
+            c => c.to_string(),
+        })
+        .collect()
+}
+
+fn image_to_pixmap(img: &DynamicImage) -> AppResult<tiny_skia::Pixmap> {
+    let rgba = img.to_rgba8();
+    let size = tiny_skia::IntSize::from_wh(rgba.width(), rgba.height())
+        .ok_or_else(|| AppError::Processing("Cannot watermark an empty image".to_string()))?;
+
+    let data = rgba
+        .pixels()
+        .flat_map(|p| {
+            let c = tiny_skia::ColorU8::from_rgba(p[0], p[1], p[2], p[3]).premultiply();
+            [c.red(), c.green(), c.blue(), c.alpha()]
+        })
+        .collect();
+
+    tiny_skia::Pixmap::from_vec(data, size)
+        .ok_or_else(|| AppError::Processing("Failed to create image from buffer".to_string()))
+}
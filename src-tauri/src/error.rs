@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize, Serializer};
+use std::path::Path;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -14,6 +16,12 @@ pub enum AppError {
     #[error("Processing error: {0}")]
     Processing(String),
 
+    #[error("Decoding error: {0}")]
+    Decode(String),
+
+    #[error("Encoding error: {0}")]
+    Encode(String),
+
     #[error("File not found: {0}")]
     FileNotFound(String),
 
@@ -22,6 +30,138 @@ pub enum AppError {
 
     #[error("Metadata error: {0}")]
     Metadata(String),
+
+    #[error("Image too large: {0}")]
+    LimitExceeded(String),
+
+    #[error("Disk full: {0}")]
+    DiskFull(String),
+
+    #[error("Window error: {0}")]
+    Tauri(#[from] tauri::Error),
+
+    #[error("{source}")]
+    WithPath {
+        path: String,
+        #[source]
+        source: Box<AppError>,
+    },
 }
 
 pub type AppResult<T> = std::result::Result<T, AppError>;
+
+/// Stable error codes the frontend can match on
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum ErrorCode {
+    NotFound,
+    PermissionDenied,
+    DecodeFailed,
+    UnsupportedFormat,
+    InvalidConfig,
+    EncodeFailed,
+    DiskFull,
+    IoFailed,
+    ProcessingFailed,
+    MetadataFailed,
+    LimitExceeded,
+    Internal,
+}
+
+/// Serialized form of an `AppError`, shared by command results and
+/// `ProcessResult.error`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorInfo {
+    pub code: ErrorCode,
+    pub message: String,
+    pub path: Option<String>,
+    pub details: Option<String>,
+}
+
+impl AppError {
+    /// Attach the file the error refers to
+    pub fn with_path(self, path: &Path) -> Self {
+        match self {
+            AppError::WithPath { .. } => self,
+            other => AppError::WithPath {
+                path: path.to_string_lossy().to_string(),
+                source: Box::new(other),
+            },
+        }
+    }
+
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            AppError::Io(e) => io_error_code(e),
+            AppError::Image(e) => match e {
+                image::ImageError::Decoding(_) => ErrorCode::DecodeFailed,
+                image::ImageError::Encoding(_) => ErrorCode::EncodeFailed,
+                image::ImageError::Parameter(_) => ErrorCode::InvalidConfig,
+                image::ImageError::Limits(_) => ErrorCode::LimitExceeded,
+                image::ImageError::Unsupported(_) => ErrorCode::UnsupportedFormat,
+                image::ImageError::IoError(e) => io_error_code(e),
+            },
+            AppError::InvalidConfig(_) => ErrorCode::InvalidConfig,
+            AppError::Processing(_) => ErrorCode::ProcessingFailed,
+            AppError::Decode(_) => ErrorCode::DecodeFailed,
+            AppError::Encode(_) => ErrorCode::EncodeFailed,
+            AppError::FileNotFound(_) => ErrorCode::NotFound,
+            AppError::UnsupportedFormat(_) => ErrorCode::UnsupportedFormat,
+            AppError::Metadata(_) => ErrorCode::MetadataFailed,
+            AppError::LimitExceeded(_) => ErrorCode::LimitExceeded,
+            AppError::DiskFull(_) => ErrorCode::DiskFull,
+            AppError::Tauri(_) => ErrorCode::Internal,
+            AppError::WithPath { source, .. } => source.code(),
+        }
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            AppError::WithPath { path, .. } => Some(path),
+            AppError::FileNotFound(path) => Some(path),
+            _ => None,
+        }
+    }
+
+    pub fn to_info(&self) -> ErrorInfo {
+        let inner = match self {
+            AppError::WithPath { source, .. } => source.as_ref(),
+            other => other,
+        };
+
+        ErrorInfo {
+            code: self.code(),
+            message: inner.to_string(),
+            path: self.path().map(str::to_string),
+            details: std::error::Error::source(inner)
+                .and_then(|e| e.source())
+                .map(|e| e.to_string()),
+        }
+    }
+}
+
+fn io_error_code(err: &std::io::Error) -> ErrorCode {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => ErrorCode::NotFound,
+        std::io::ErrorKind::PermissionDenied => ErrorCode::PermissionDenied,
+        std::io::ErrorKind::StorageFull | std::io::ErrorKind::QuotaExceeded => ErrorCode::DiskFull,
+        _ => ErrorCode::IoFailed,
+    }
+}
+
+impl Serialize for AppError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.to_info().serialize(serializer)
+    }
+}
+
+/// Adds file context to fallible operations
+pub trait ResultExt<T> {
+    fn with_path(self, path: &Path) -> AppResult<T>;
+}
+
+impl<T, E: Into<AppError>> ResultExt<T> for Result<T, E> {
+    fn with_path(self, path: &Path) -> AppResult<T> {
+        self.map_err(|e| e.into().with_path(path))
+    }
+}
@@ -12,13 +12,25 @@ pub fn run() {
         .plugin(tauri_plugin_opener::init())
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_fs::init())
+        .register_asynchronous_uri_scheme_protocol(PREVIEW_SCHEME, |ctx, request, responder| {
+            let app_handle = ctx.app_handle().clone();
+            tauri::async_runtime::spawn(async move {
+                responder.respond(handle_preview_request(app_handle, request).await);
+            });
+        })
         .invoke_handler(tauri::generate_handler![
             scan_directory,
             get_file_info_command,
             process_single_image,
             process_batch_images,
+            clear_process_cache,
+            begin_in_place_run,
+            undo_run,
             generate_image_preview,
             get_image_preview,
+            preview_compression,
+            compare_output,
+            clear_thumbnail_cache,
             open_output_folder,
             minimize_window,
             maximize_window,
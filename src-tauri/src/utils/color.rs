@@ -0,0 +1,56 @@
+/// Parses `#rgb`, `#rrggbb` or `#rrggbbaa` (the `#` is optional) into RGBA
+pub fn parse_hex_color(value: &str) -> Option<[u8; 4]> {
+    let value = value.trim();
+    let hex = value.strip_prefix('#').unwrap_or(value);
+    // from_str_radix would also take a sign, e.g. "+f"
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    let channel = |i: usize, len: usize| u8::from_str_radix(&hex[i..i + len], 16).ok();
+
+    match hex.len() {
+        3 => Some([
+            channel(0, 1)? * 17,
+            channel(1, 1)? * 17,
+            channel(2, 1)? * 17,
+            255,
+        ]),
+        6 => Some([channel(0, 2)?, channel(2, 2)?, channel(4, 2)?, 255]),
+        8 => Some([
+            channel(0, 2)?,
+            channel(2, 2)?,
+            channel(4, 2)?,
+            channel(6, 2)?,
+        ]),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_every_length() {
+        assert_eq!(parse_hex_color("#fa0"), Some([255, 170, 0, 255]));
+        assert_eq!(parse_hex_color("#1E90ff"), Some([30, 144, 255, 255]));
+        assert_eq!(parse_hex_color("#00000080"), Some([0, 0, 0, 128]));
+    }
+
+    #[test]
+    fn hash_and_whitespace_are_optional() {
+        assert_eq!(parse_hex_color(" 336699 "), Some([51, 102, 153, 255]));
+    }
+
+    #[test]
+    fn rejects_malformed_colors() {
+        for value in [
+            "", "#", "#ff", "#ffff", "#12345", "#ggg", "##fff", "#+f0000", "#ff ff00",
+        ] {
+            assert_eq!(parse_hex_color(value), None, "{value:?}");
+        }
+        // Multi-byte characters must not split a channel mid-character
+        assert_eq!(parse_hex_color("#ffé0"), None);
+    }
+}
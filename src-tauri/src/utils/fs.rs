@@ -1,4 +1,11 @@
+use crate::error::{AppError, AppResult};
+use std::fs::File;
+use std::io::{ErrorKind, Write};
 use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Keeps temp names unique when several workers write to one folder
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
 
 pub fn format_file_size(bytes: u64) -> String {
     const KB: u64 = 1024;
@@ -21,3 +28,54 @@ pub fn get_file_extension(path: &Path) -> Option<String> {
         .and_then(|s| s.to_str())
         .map(|s| s.to_lowercase())
 }
+
+/// Writes a file so that readers only ever see the old contents or the
+/// complete new ones. The data goes to a hidden temp file in the same
+/// directory, is flushed to disk and then renamed over `path`; the temp
+/// file is removed if any step fails.
+pub fn write_atomic(path: &Path, data: &[u8]) -> AppResult<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy())
+        .unwrap_or_default();
+    let temp = dir.join(format!(
+        ".{}.{}-{}.tmp",
+        name,
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+
+    let written = File::options()
+        .write(true)
+        .create_new(true)
+        .open(&temp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .and_then(|()| std::fs::rename(&temp, path));
+
+    if let Err(e) = written {
+        let _ = std::fs::remove_file(&temp);
+        let err = match e.kind() {
+            ErrorKind::StorageFull | ErrorKind::QuotaExceeded => AppError::DiskFull(format!(
+                "No space left to write {}",
+                format_file_size(data.len() as u64)
+            )),
+            _ => AppError::Io(e),
+        };
+        return Err(err.with_path(path));
+    }
+
+    // Make the rename itself durable; Windows can't open directories for this
+    #[cfg(unix)]
+    if let Ok(dir) = File::open(dir) {
+        let _ = dir.sync_all();
+    }
+
+    Ok(())
+}
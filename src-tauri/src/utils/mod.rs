@@ -1,3 +1,5 @@
+pub mod color;
 pub mod fs;
 
+pub use color::*;
 pub use fs::*;
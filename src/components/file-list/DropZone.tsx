@@ -16,7 +16,7 @@ export function DropZone() {
                     multiple: true,
                     filters: [{
                         name: "Images",
-                        extensions: ["png", "jpg", "jpeg", "webp", "bmp", "tiff", "gif"],
+                        extensions: ["png", "jpg", "jpeg", "webp", "bmp", "tiff", "gif", "svg", "svgz"],
                     }],
                 }
             });
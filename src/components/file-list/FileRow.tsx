@@ -1,6 +1,11 @@
 import { FileQueueItem, OutputFormat } from "../../types";
 import { useAppStore } from "../../store/appStore";
 import { Trash2, Eye, FolderOpen } from "lucide-react";
+import { previewUrl } from "../preview/previewUrl";
+
+// Matches the size the backend pre-generates after a scan, so these come
+// straight from the thumbnail cache
+const THUMBNAIL_SIZE = 128;
 
 interface FileRowProps {
     file: FileQueueItem;
@@ -100,6 +105,12 @@ export function FileRow({ file, index, formatSize }: FileRowProps) {
                 />
             </div>
             <div className="col-name" title={file.path}>
+                <img
+                    className="file-thumbnail"
+                    src={previewUrl(file.path, THUMBNAIL_SIZE)}
+                    loading="lazy"
+                    alt=""
+                />
                 {file.name}
             </div>
             <div className="col-output-type">
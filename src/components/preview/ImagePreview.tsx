@@ -1,17 +1,29 @@
 import { useEffect, useState } from "react";
-import { useAppStore } from "../../store/appStore";
+import { useAppStore, buildProcessConfig } from "../../store/appStore";
+import { CompressionPreview, ImageComparison } from "../../types";
 import { X, ZoomIn, ZoomOut, Maximize2 } from "lucide-react";
 import { invoke } from "@tauri-apps/api/core";
+import { previewUrl } from "./previewUrl";
+
+// Big enough to stay sharp when zoomed in on a high density display
+const PREVIEW_SIZE = 1600;
+
+// Settings changes wait this long, so dragging a slider encodes once
+const DEBOUNCE_MS = 300;
 
 export function ImagePreview() {
     const showPreview = useAppStore((state) => state.showPreview);
     const previewFileId = useAppStore((state) => state.previewFileId);
     const setPreview = useAppStore((state) => state.setPreview);
     const files = useAppStore((state) => state.files);
+    const config = useAppStore((state) => state.config);
+    const basePath = useAppStore((state) => state.basePath);
 
-    const [view, setView] = useState<"original" | "compressed">("original");
+    const [view, setView] = useState<"original" | "compressed" | "difference">("original");
     const [originalSrc, setOriginalSrc] = useState<string>("");
     const [compressedSrc, setCompressedSrc] = useState<string>("");
+    const [estimate, setEstimate] = useState<CompressionPreview | null>(null);
+    const [comparison, setComparison] = useState<ImageComparison | null>(null);
     const [loading, setLoading] = useState(true);
     const [zoom, setZoom] = useState(100);
 
@@ -20,35 +32,64 @@ export function ImagePreview() {
     useEffect(() => {
         if (!file || !showPreview) return;
 
+        // Results of an earlier run that finish late are dropped
+        let cancelled = false;
+
         const loadPreviews = async () => {
             setLoading(true);
+            setCompressedSrc("");
+            setEstimate(null);
+            setComparison(null);
             try {
-                // Load original image preview
-                const originalPreview = await invoke<string>("get_image_preview", {
-                    filePath: file.path,
-                });
-                setOriginalSrc(`data:image/jpeg;base64,${originalPreview}`);
+                setOriginalSrc(previewUrl(file.path, PREVIEW_SIZE));
 
-                // Load compressed preview if available
+                // Saved output when processed, otherwise a dry run that also
+                // returns the difference, so the image is encoded only once
                 if (file.status === "completed" && file.result?.outputPath) {
+                    setCompressedSrc(previewUrl(file.result.outputPath, PREVIEW_SIZE));
+                    try {
+                        const result = await invoke<ImageComparison>("compare_output", {
+                            path: file.path,
+                            outputPath: file.result.outputPath,
+                            // Only its decode limits are used with an output file
+                            config: buildProcessConfig(config, basePath),
+                            maxSize: PREVIEW_SIZE,
+                        });
+                        if (!cancelled) setComparison(result);
+                    } catch (err) {
+                        console.error("Failed to compare images:", err);
+                    }
+                } else {
+                    // Dry run with the current settings, nothing is written
                     try {
-                        const compressedPreview = await invoke<string>("get_image_preview", {
-                            filePath: file.result.outputPath,
+                        const dryRun = await invoke<CompressionPreview>("preview_compression", {
+                            path: file.path,
+                            config: buildProcessConfig(config, basePath),
+                            maxSize: PREVIEW_SIZE,
+                            compare: true,
                         });
-                        setCompressedSrc(`data:image/jpeg;base64,${compressedPreview}`);
+                        if (!cancelled) {
+                            setCompressedSrc(`data:${dryRun.mimeType};base64,${dryRun.preview}`);
+                            setEstimate(dryRun);
+                            setComparison(dryRun.comparison ?? null);
+                        }
                     } catch (err) {
-                        console.error("Failed to load compressed preview:", err);
+                        console.error("Failed to run compression preview:", err);
                     }
                 }
             } catch (error) {
                 console.error("Failed to load preview:", error);
             } finally {
-                setLoading(false);
+                if (!cancelled) setLoading(false);
             }
         };
 
-        loadPreviews();
-    }, [file, showPreview]);
+        const timer = setTimeout(loadPreviews, DEBOUNCE_MS);
+        return () => {
+            cancelled = true;
+            clearTimeout(timer);
+        };
+    }, [file, showPreview, config, basePath]);
 
     if (!showPreview || !file) return null;
 
@@ -61,7 +102,7 @@ export function ImagePreview() {
     const handleZoomOut = () => setZoom((z) => Math.max(z - 25, 25));
     const handleZoomReset = () => setZoom(100);
 
-    const canShowCompressed = file.status === "completed" && compressedSrc;
+    const canShowCompressed = !!compressedSrc;
 
     return (
         <div className="preview-modal-overlay" onClick={handleClose}>
@@ -74,6 +115,8 @@ export function ImagePreview() {
                             {file.size ? `${(file.size / 1024 / 1024).toFixed(2)} MB` : ""}
                             {file.result && ` → ${(file.result.newSize / 1024 / 1024).toFixed(2)} MB`}
                             {file.result && ` (${file.result.compressionRatio.toFixed(1)}%)`}
+                            {!file.result && estimate &&
+                                ` → ~${(estimate.newSize / 1024 / 1024).toFixed(2)} MB (${estimate.compressionRatio.toFixed(1)}%, ${estimate.width}×${estimate.height})`}
                         </p>
                     </div>
 
@@ -108,6 +151,16 @@ export function ImagePreview() {
                         disabled={!canShowCompressed}
                     >
                         Compressed {!canShowCompressed && "(Not available)"}
+                        {canShowCompressed && estimate && " (preview)"}
+                    </button>
+                    <button
+                        className={view === "difference" ? "active" : ""}
+                        onClick={() => setView("difference")}
+                        disabled={!comparison}
+                    >
+                        Difference
+                        {comparison &&
+                            ` (${comparison.psnr != null ? `${comparison.psnr.toFixed(1)} dB` : "identical"})`}
                     </button>
                 </div>
 
@@ -127,6 +180,16 @@ export function ImagePreview() {
                                     style={{ transform: `scale(${zoom / 100})` }}
                                 />
                             )}
+                            {view === "difference" && comparison && (
+                                <img
+                                    src={`data:image/png;base64,${comparison.heatmap}`}
+                                    alt="Difference heatmap"
+                                    title={comparison.regions
+                                        .map((r) => `${r.x},${r.y} ${r.width}×${r.height}: mean ${r.meanError.toFixed(1)}, max ${r.maxError}`)
+                                        .join("\n")}
+                                    style={{ transform: `scale(${zoom / 100})` }}
+                                />
+                            )}
                             {view === "compressed" && compressedSrc && (
                                 <img
                                     src={compressedSrc}
@@ -0,0 +1,6 @@
+import { convertFileSrc } from "@tauri-apps/api/core";
+
+// Previews are served by the backend's preview:// protocol, which caches
+// them and rounds the size up to one it keeps thumbnails for
+export const previewUrl = (path: string, size: number) =>
+    `${convertFileSrc(path, "preview")}?size=${size}`;
@@ -20,6 +20,8 @@ export function FormatSettings() {
                     <option value={OutputFormat.Png}>PNG</option>
                     <option value={OutputFormat.Jpeg}>JPEG</option>
                     <option value={OutputFormat.WebP}>WebP</option>
+                    <option value={OutputFormat.Gif}>GIF</option>
+                    <option value={OutputFormat.Auto}>Auto (smallest per image)</option>
                 </select>
             </div>
 
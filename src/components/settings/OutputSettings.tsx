@@ -6,7 +6,32 @@ import { invoke } from "@tauri-apps/api/core";
 export function OutputSettings() {
     const outputPath = useAppStore((state) => state.config.outputPath);
     const updateConfig = useAppStore((state) => state.updateConfig);
+    const limits = useAppStore((state) => state.config.limits);
+    const inPlace = useAppStore((state) => state.config.inPlace ?? false);
+    const incremental = useAppStore((state) => state.config.incremental ?? false);
+    const fixOutputExtension = useAppStore((state) => state.config.fixOutputExtension ?? false);
+    const lastRunDir = useAppStore((state) => state.lastRunDir);
+    const undoLastRun = useAppStore((state) => state.undoLastRun);
     const [localPath, setLocalPath] = useState(outputPath);
+    const [cacheMessage, setCacheMessage] = useState("");
+
+    const handleClearThumbnails = async () => {
+        try {
+            const freed = await invoke<number>("clear_thumbnail_cache");
+            setCacheMessage(`Freed ${(freed / 1024 / 1024).toFixed(1)} MB`);
+        } catch (error) {
+            console.error("Failed to clear thumbnail cache:", error);
+        }
+    };
+
+    const handleClearProcessCache = async () => {
+        try {
+            const freed = await invoke<number>("clear_process_cache");
+            setCacheMessage(`Forgot earlier runs, freed ${(freed / 1024).toFixed(1)} KB`);
+        } catch (error) {
+            console.error("Failed to clear process cache:", error);
+        }
+    };
 
     const handleSelectFolder = async () => {
         try {
@@ -15,7 +40,7 @@ export function OutputSettings() {
                 options: {
                     directory: true,
                     multiple: false,
-                    title: "Select Output Folder",
+                    title: inPlace ? "Select Backup Folder" : "Select Output Folder",
                 }
             });
 
@@ -33,7 +58,27 @@ export function OutputSettings() {
             <h3>Output</h3>
 
             <div className="form-group">
-                <label>Output Folder</label>
+                <label className="checkbox-label">
+                    <input
+                        type="checkbox"
+                        checked={inPlace}
+                        onChange={(e) => updateConfig({ inPlace: e.target.checked })}
+                    />
+                    <span>Optimize in place</span>
+                </label>
+                <p className="hint">
+                    Replace originals with the optimized files, keeping their names and formats.
+                    Originals are moved to a timestamped folder inside the backup folder.
+                </p>
+                {lastRunDir && (
+                    <button onClick={undoLastRun} className="btn-secondary">
+                        Undo last in-place run
+                    </button>
+                )}
+            </div>
+
+            <div className="form-group">
+                <label>{inPlace ? "Backup Folder" : "Output Folder"}</label>
                 <div className="input-with-button">
                     <input
                         type="text"
@@ -89,6 +134,59 @@ export function OutputSettings() {
                     Processed images will be saved to this folder with "_optimized" suffix.
                 </p>
             </div>
+
+            <div className="form-group">
+                <label className="checkbox-label">
+                    <input
+                        type="checkbox"
+                        checked={incremental}
+                        onChange={(e) => updateConfig({ incremental: e.target.checked })}
+                    />
+                    <span>Skip unchanged files</span>
+                </label>
+                <p className="hint">
+                    Files whose contents and settings match an earlier run keep their previous output.
+                    Not used for in-place runs.
+                </p>
+                <button onClick={handleClearProcessCache} className="btn-secondary">
+                    Forget earlier runs
+                </button>
+            </div>
+
+            <div className="form-group">
+                <label className="checkbox-label">
+                    <input
+                        type="checkbox"
+                        checked={fixOutputExtension}
+                        onChange={(e) => updateConfig({ fixOutputExtension: e.target.checked })}
+                    />
+                    <span>Fix output extensions</span>
+                </label>
+                <p className="hint">
+                    Outputs are named after the format they were actually written in.
+                    Input files keep their names.
+                </p>
+            </div>
+
+            <div className="form-group">
+                <label>Largest image (megapixels)</label>
+                <input
+                    type="number"
+                    min={1}
+                    value={Math.round((limits?.maxPixels ?? 250_000_000) / 1_000_000)}
+                    onChange={(e) => updateConfig({
+                        limits: { ...limits, maxPixels: Math.max(1, Number(e.target.value)) * 1_000_000 }
+                    })}
+                />
+                <p className="hint">
+                    Larger images are rejected before decoding instead of running out of memory.
+                </p>
+            </div>
+
+            <div className="form-group">
+                <button onClick={handleClearThumbnails}>Clear thumbnail cache</button>
+                {cacheMessage && <p className="hint">{cacheMessage}</p>}
+            </div>
         </div>
     );
 }
@@ -1,5 +1,5 @@
 import { useAppStore } from "../../store/appStore";
-import { OutputFormat, PngEncoder, JpegEncoder, WebPEncoder } from "../../types";
+import { OutputFormat, PngEncoder, JpegEncoder, JpegTransform, WebPEncoder } from "../../types";
 
 export function QualitySettings() {
     const config = useAppStore((state) => state.config);
@@ -52,7 +52,7 @@ export function QualitySettings() {
                                 <label>
                                     Quality: {config.pngOptions.quality}
                                     <span className="label-hint">
-                                        (Higher = More colors, larger file)
+                                        (Target; higher = more colors, larger file)
                                     </span>
                                 </label>
                                 <input
@@ -66,6 +66,56 @@ export function QualitySettings() {
                                 />
                             </div>
 
+                            <div className="form-group">
+                                <label>
+                                    Minimum quality: {config.pngOptions.minQuality ?? 0}
+                                    <span className="label-hint">
+                                        (Saved lossless when not reachable)
+                                    </span>
+                                </label>
+                                <input
+                                    type="range"
+                                    min="0"
+                                    max="100"
+                                    value={config.pngOptions.minQuality ?? 0}
+                                    onChange={(e) =>
+                                        updatePngOptions({ minQuality: parseInt(e.target.value) })
+                                    }
+                                />
+                            </div>
+
+                            <div className="form-group">
+                                <label>
+                                    Speed: {config.pngOptions.speed ?? 4}
+                                    <span className="label-hint">(1 = best, 10 = fastest)</span>
+                                </label>
+                                <input
+                                    type="range"
+                                    min="1"
+                                    max="10"
+                                    value={config.pngOptions.speed ?? 4}
+                                    onChange={(e) =>
+                                        updatePngOptions({ speed: parseInt(e.target.value) })
+                                    }
+                                />
+                            </div>
+
+                            <div className="form-group">
+                                <label>
+                                    Posterize: {config.pngOptions.posterize ?? 0}
+                                    <span className="label-hint">(bits dropped per channel)</span>
+                                </label>
+                                <input
+                                    type="range"
+                                    min="0"
+                                    max="4"
+                                    value={config.pngOptions.posterize ?? 0}
+                                    onChange={(e) =>
+                                        updatePngOptions({ posterize: parseInt(e.target.value) })
+                                    }
+                                />
+                            </div>
+
                             <div className="form-group">
                                 <label className="checkbox-label">
                                     <input
@@ -156,6 +206,49 @@ export function QualitySettings() {
                             <span>optimize Huffman tables</span>
                         </label>
                     </div>
+
+                    <div className="form-group">
+                        <label className="checkbox-label">
+                            <input
+                                type="checkbox"
+                                checked={config.jpegOptions.lossless ?? false}
+                                onChange={(e) =>
+                                    updateJpegOptions({ lossless: e.target.checked })
+                                }
+                            />
+                            <span>lossless for JPEG inputs (no resize or edits)</span>
+                        </label>
+                    </div>
+
+                    <div className="form-group">
+                        <label>Rotate / Flip</label>
+                        <select
+                            value={config.jpegOptions.transform ?? JpegTransform.None}
+                            onChange={(e) =>
+                                updateJpegOptions({ transform: e.target.value as JpegTransform })
+                            }
+                        >
+                            <option value={JpegTransform.None}>None</option>
+                            <option value={JpegTransform.Rotate90}>Rotate 90°</option>
+                            <option value={JpegTransform.Rotate180}>Rotate 180°</option>
+                            <option value={JpegTransform.Rotate270}>Rotate 270°</option>
+                            <option value={JpegTransform.FlipHorizontal}>Flip horizontal</option>
+                            <option value={JpegTransform.FlipVertical}>Flip vertical</option>
+                        </select>
+                    </div>
+
+                    <div className="form-group">
+                        <label className="checkbox-label">
+                            <input
+                                type="checkbox"
+                                checked={config.jpegOptions.trimEdges ?? false}
+                                onChange={(e) =>
+                                    updateJpegOptions({ trimEdges: e.target.checked })
+                                }
+                            />
+                            <span>trim edges that can't be rotated losslessly</span>
+                        </label>
+                    </div>
                 </>
             )}
 
@@ -1,9 +1,11 @@
 import { create } from "zustand";
 import { persist, createJSONStorage } from "zustand/middleware";
 import {
+    AppError,
+    BatchSummary,
     FileQueueItem,
     ProcessConfig,
-    ProcessResult,
+    UndoSummary,
     OutputFormat,
     ResizeMode,
     ResizeAlgorithm,
@@ -14,6 +16,9 @@ import {
     WebPEncoder,
 } from "../types";
 
+// Files sent to the backend per batch call
+const BATCH_CHUNK_SIZE = 32;
+
 type StatusFilter = "all" | "pending" | "processing" | "completed" | "error";
 
 interface LogEntry {
@@ -28,6 +33,7 @@ interface AppState {
     selectedFiles: Set<string>;
     statusFilter: StatusFilter;
     basePath: string | null;  // Base path for folder structure
+    lastRunDir: string | null;  // Backup folder of the last in-place run
 
     // Processing config
     config: ProcessConfig;
@@ -61,6 +67,7 @@ interface AppState {
     updateFileStatus: (id: string, status: FileQueueItem["status"], result?: FileQueueItem["result"]) => void;
     stopProcessing: () => void;
     startProcessing: () => Promise<void>;
+    undoLastRun: () => Promise<void>;
 
     setStatusFilter: (filter: StatusFilter) => void;
     getFilteredFiles: () => FileQueueItem[];
@@ -73,6 +80,56 @@ interface AppState {
     clearLogs: () => void;
 }
 
+/**
+ * Converts the settings UI state into the backend's ProcessConfig.
+ * `runDir` is the backup folder of the in-place run being processed.
+ */
+export function buildProcessConfig(config: ProcessConfig, basePath: string | null, runDir: string | null = null) {
+    // Transform config to match Rust format
+    const getModeKey = (mode: ResizeMode): string => {
+        // Convert enum to camelCase key
+        return mode; // Already camelCase after enum fix
+    };
+
+    return {
+        outputFormat: config.outputFormat,
+        resize: {
+            enabled: config.resize.enabled,
+            mode: config.resize.enabled ? {
+                [getModeKey(config.resize.mode)]: config.resize.mode === ResizeMode.Percentage
+                    ? { scale: config.resize.scale || 100 }
+                    : config.resize.mode === ResizeMode.FixedWidth
+                        ? { width: config.resize.width || 800 }
+                        : config.resize.mode === ResizeMode.FixedHeight
+                            ? { height: config.resize.height || 600 }
+                            : config.resize.mode === ResizeMode.Exact
+                                ? { width: config.resize.width || 800, height: config.resize.height || 600 }
+                                : config.resize.mode === ResizeMode.FitBox
+                                    ? { maxWidth: config.resize.maxWidth || 1920, maxHeight: config.resize.maxHeight || 1080 }
+                                    : { width: config.resize.width || 800, height: config.resize.height || 600 }
+            } : { percentage: { scale: 100 } },
+            algorithm: config.resize.algorithm,
+        },
+        pngOptions: config.pngOptions,
+        jpegOptions: config.jpegOptions,
+        webpOptions: config.webpOptions,
+        gifOptions: config.gifOptions,
+        svgOptions: config.svgOptions,
+        adjustments: config.adjustments,
+        matte: config.matte,
+        watermark: config.watermark,
+        limits: config.limits,
+        metadataMode: config.metadataMode,
+        fileConflictMode: config.fileConflictMode,
+        preserveFolderStructure: config.preserveFolderStructure,
+        basePath,
+        outputPath: config.outputPath,
+        incremental: config.incremental ?? false,
+        fixOutputExtension: config.fixOutputExtension ?? false,
+        inPlace: runDir ? { backupDir: runDir } : null,
+    };
+}
+
 export const useAppStore = create<AppState>()(
     persist(
         (set, get) => ({
@@ -81,6 +138,7 @@ export const useAppStore = create<AppState>()(
             selectedFiles: new Set(),
             statusFilter: "all",
             basePath: null,
+            lastRunDir: null,
 
             config: {
                 outputFormat: OutputFormat.Jpeg,  // Changed from Png to Jpeg for better JPG compression
@@ -113,6 +171,8 @@ export const useAppStore = create<AppState>()(
                 fileConflictMode: FileConflictMode.Rename,
                 preserveFolderStructure: false,
                 outputPath: "/tmp/optimized",
+                incremental: false,
+                fixOutputExtension: false,
             },
 
             isProcessing: false,
@@ -234,93 +294,122 @@ export const useAppStore = create<AppState>()(
                 get().addLog("info", `🚀 Starting batch processing...`);
                 get().addLog("info", `📁 Processing ${filesToProcess.length} file(s)`);
 
-                for (let i = 0; i < filesToProcess.length; i++) {
-                    const currentState = get();
-                    if (currentState.processingAborted) break;
+                // In-place runs move every original into one backup folder
+                let runDir: string | null = null;
+                if (state.config.inPlace) {
+                    try {
+                        const { invoke } = await import("@tauri-apps/api/core");
+                        runDir = await invoke<string>("begin_in_place_run", {
+                            backupRoot: state.config.outputPath,
+                        });
+                        set({ lastRunDir: runDir });
+                        get().addLog("info", `🗂️ Backing up originals to ${runDir}`);
+                    } catch (error) {
+                        const message = (error as AppError)?.message ?? String(error);
+                        get().addLog("error", `❌ Couldn't create backup folder: ${message}`);
+                        set({ isProcessing: false });
+                        return;
+                    }
+                }
+
+                let processed = 0;
+                let cached = 0;
+                let failed = 0;
 
-                    const file = filesToProcess[i];
-                    set({ currentProcessingIndex: i });
+                const rustConfig = buildProcessConfig(state.config, state.basePath, runDir);
 
-                    // Update status to processing
-                    get().updateFileStatus(file.id, "processing");
+                // The backend schedules the files of a chunk together; stopping
+                // takes effect between chunks
+                for (let start = 0; start < filesToProcess.length; start += BATCH_CHUNK_SIZE) {
+                    if (get().processingAborted) break;
 
-                    // Log file processing
-                    get().addLog("info", `⚙️ Processing: ${file.name} (${(file.size / 1024 / 1024).toFixed(2)} MB)`);
+                    const chunk = filesToProcess.slice(start, start + BATCH_CHUNK_SIZE);
+                    set({ currentProcessingIndex: start });
 
+                    chunk.forEach((file) => {
+                        get().updateFileStatus(file.id, "processing");
+                        get().addLog("info", `⚙️ Processing: ${file.name} (${(file.size / 1024 / 1024).toFixed(2)} MB)`);
+                    });
+
+                    let summary: BatchSummary;
                     try {
-                        // Import invoke dynamically
                         const { invoke } = await import("@tauri-apps/api/core");
-
-                        // Transform config to match Rust format
-                        const getModeKey = (mode: ResizeMode): string => {
-                            // Convert enum to camelCase key
-                            return mode; // Already camelCase after enum fix
-                        };
-
-                        const rustConfig = {
-                            outputFormat: state.config.outputFormat,
-                            resize: {
-                                enabled: state.config.resize.enabled,
-                                mode: state.config.resize.enabled ? {
-                                    [getModeKey(state.config.resize.mode)]: state.config.resize.mode === ResizeMode.Percentage
-                                        ? { scale: state.config.resize.scale || 100 }
-                                        : state.config.resize.mode === ResizeMode.FixedWidth
-                                            ? { width: state.config.resize.width || 800 }
-                                            : state.config.resize.mode === ResizeMode.FixedHeight
-                                                ? { height: state.config.resize.height || 600 }
-                                                : state.config.resize.mode === ResizeMode.Exact
-                                                    ? { width: state.config.resize.width || 800, height: state.config.resize.height || 600 }
-                                                    : state.config.resize.mode === ResizeMode.FitBox
-                                                        ? { maxWidth: state.config.resize.maxWidth || 1920, maxHeight: state.config.resize.maxHeight || 1080 }
-                                                        : { width: state.config.resize.width || 800, height: state.config.resize.height || 600 }
-                                } : { percentage: { scale: 100 } },
-                                algorithm: state.config.resize.algorithm,
-                            },
-                            pngOptions: state.config.pngOptions,
-                            jpegOptions: state.config.jpegOptions,
-                            webpOptions: state.config.webpOptions,
-                            metadataMode: state.config.metadataMode,
-                            fileConflictMode: state.config.fileConflictMode,
-                            preserveFolderStructure: state.config.preserveFolderStructure,
-                            basePath: state.basePath,
-                            outputPath: state.config.outputPath,
-                        };
-
-                        // Call backend process command
-                        const result = await invoke<ProcessResult>("process_single_image", {
-                            inputPath: file.path,
+                        summary = await invoke<BatchSummary>("process_batch_images", {
+                            inputPaths: chunk.map((file) => file.path),
                             config: rustConfig,
                         });
+                    } catch (error) {
+                        console.error("Processing error:", error);
+                        const message = (error as AppError)?.message ?? String(error);
+                        chunk.forEach((file) => get().updateFileStatus(file.id, "error"));
+                        failed += chunk.length;
+                        get().addLog("error", `❌ Batch failed: ${message}`);
+                        break;
+                    }
 
-                        // Update with result
-                        get().updateFileStatus(file.id, "completed", {
-                            success: result.success,
-                            originalSize: result.originalSize,
-                            newSize: result.newSize,
-                            compressionRatio: result.compressionRatio,
-                            outputPath: result.outputPath,
-                            error: result.error,
-                        });
+                    // Results come back in the order the paths were sent
+                    chunk.forEach((file, i) => {
+                        const result = summary.results[i];
+                        if (!result.success) {
+                            get().updateFileStatus(file.id, "error", result);
+                            get().addLog("error", `❌ ${file.name} - Error: ${result.error?.message ?? "Unknown error"}`);
+                            return;
+                        }
+
+                        get().updateFileStatus(file.id, "completed", result);
+                        if (result.cached) {
+                            get().addLog("info", `♻️ ${file.name} - Unchanged, kept previous output`);
+                            return;
+                        }
+                        if (result.unchanged) {
+                            get().addLog("info", `➖ ${file.name} - Already optimal, kept original`);
+                            return;
+                        }
 
                         // Log success
                         const savedMB = ((result.originalSize - result.newSize) / 1024 / 1024).toFixed(2);
                         const ratio = result.compressionRatio.toFixed(1);
                         get().addLog("success", `✅ ${file.name} - Saved ${savedMB} MB (${ratio}%)`);
-                    } catch (error) {
-                        console.error("Processing error:", error);
-                        get().updateFileStatus(file.id, "error");
+                    });
+
+                    processed += summary.processed;
+                    cached += summary.cached;
+                    failed += summary.failed;
 
-                        // Log error
-                        get().addLog("error", `❌ ${file.name} - Error: ${error}`);
+                    // Every remaining file would fail the same way
+                    if (summary.stopped) {
+                        get().addLog("error", "💾 Output disk is full, stopping batch");
+                        break;
                     }
                 }
 
                 set({ isProcessing: false, processingAborted: false });
 
                 // Log completion
-                const completed = get().files.filter(f => f.status === "completed").length;
-                const errors = get().files.filter(f => f.status === "error").length;
-                get().addLog("success", `🎉 Batch complete! ${completed} succeeded, ${errors} failed`);
+                get().addLog("success", `🎉 Batch complete! ${processed} processed, ${cached} unchanged, ${failed} failed`);
+            },
+
+            undoLastRun: async () => {
+                const runDir = get().lastRunDir;
+                if (!runDir) return;
+
+                try {
+                    const { invoke } = await import("@tauri-apps/api/core");
+                    const summary = await invoke<UndoSummary>("undo_run", { runDir });
+
+                    get().addLog("success", `↩️ Restored ${summary.restored} original(s)`);
+                    summary.errors.forEach((error) =>
+                        get().addLog("error", `❌ ${error.path ?? ""} - ${error.message}`)
+                    );
+
+                    // Keep the run around so the remaining files can be retried
+                    if (summary.errors.length === 0) {
+                        set({ lastRunDir: null });
+                    }
+                } catch (error) {
+                    const message = (error as AppError)?.message ?? String(error);
+                    get().addLog("error", `❌ Undo failed: ${message}`);
+                }
             },
 
             setStatusFilter: (filter) =>
@@ -380,6 +469,7 @@ export const useAppStore = create<AppState>()(
                 files: state.files,
                 config: state.config,
                 basePath: state.basePath,
+                lastRunDir: state.lastRunDir,
                 statusFilter: state.statusFilter,
                 isProcessing: state.isProcessing,
                 currentProcessingIndex: state.currentProcessingIndex,
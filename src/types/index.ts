@@ -6,6 +6,36 @@ export interface FileInfo {
     width: number;
     height: number;
     format: string;
+    detectedFormat?: string;
+    extension?: string;
+    extensionMismatch: boolean;
+    hasAlpha: boolean;
+    bitDepth?: number;
+    colorType?: "Gray" | "GrayAlpha" | "RGB" | "RGBA" | "Indexed" | "CMYK" | "Alpha";
+    frameCount: number;
+    animated: boolean;
+    iccProfile?: string;
+    orientation?: number;
+    dpi?: number;
+    captureDate?: string;
+}
+
+// Directory scan options
+export type SymlinkPolicy = "skip" | "filesOnly" | "follow";
+
+export interface ScanOptions {
+    include?: string[];
+    exclude?: string[];
+    respectIgnoreFiles?: boolean;
+    skipHidden?: boolean;
+    skipSystemFolders?: boolean;
+    minFileSize?: number;
+    maxFileSize?: number;
+    minWidth?: number;
+    minHeight?: number;
+    maxWidth?: number;
+    maxHeight?: number;
+    symlinks?: SymlinkPolicy;
 }
 
 // Processing configuration
@@ -13,6 +43,8 @@ export enum OutputFormat {
     Png = "png",
     Jpeg = "jpeg",
     WebP = "webP",
+    Gif = "gif",
+    Auto = "auto",
 }
 
 export enum ResizeMode {
@@ -53,6 +85,9 @@ export interface PngOptions {
     encoder: PngEncoder;
     lossy: boolean;
     quality: number;
+    minQuality?: number;
+    speed?: number;
+    posterize?: number;
     dithering: boolean;
     preserveTransparency: boolean;
 }
@@ -68,6 +103,18 @@ export interface JpegOptions {
     quality: number;
     progressive: boolean;
     optimizeCoding: boolean;
+    lossless?: boolean;
+    transform?: JpegTransform;
+    trimEdges?: boolean;
+}
+
+export enum JpegTransform {
+    None = "none",
+    Rotate90 = "rotate90",
+    Rotate180 = "rotate180",
+    Rotate270 = "rotate270",
+    FlipHorizontal = "flipHorizontal",
+    FlipVertical = "flipVertical",
 }
 
 // WebP Encoder Options
@@ -82,6 +129,66 @@ export interface WebPOptions {
     method: number; // 0-6
 }
 
+export interface GifOptions {
+    quality: number; // 0-100
+    sharedPalette: boolean;
+    dithering: boolean;
+    preserveTransparency: boolean;
+    optimizeFrames: boolean;
+}
+
+export interface SvgOptions {
+    dpi: number; // 96 renders at the document's CSS pixel size
+    background?: string; // hex color, transparent when unset
+}
+
+export interface SharpenOptions {
+    amount: number; // percent, 0-500
+    radius: number; // blur sigma in pixels
+    threshold: number; // 0-255
+}
+
+export interface AdjustmentOptions {
+    sharpen?: SharpenOptions;
+    brightness: number; // -100 to 100
+    contrast: number; // -100 to 100
+    saturation: number; // -100 to 100
+    gamma: number; // 0.1-10, 1 = unchanged
+    grayscale: boolean;
+}
+
+export enum TransparencyPolicy {
+    Flatten = "flatten",
+    Warn = "warn",
+    Refuse = "refuse",
+}
+
+export interface MatteOptions {
+    color: string; // hex
+    onTransparency: TransparencyPolicy;
+}
+
+export type WatermarkSource =
+    | { image: { path: string } }
+    | { text: { text: string; color: string } };
+
+export enum WatermarkPosition {
+    TopLeft = "topLeft",
+    TopRight = "topRight",
+    BottomLeft = "bottomLeft",
+    BottomRight = "bottomRight",
+    Center = "center",
+}
+
+export interface WatermarkOptions {
+    source: WatermarkSource;
+    position: WatermarkPosition;
+    margin: number; // percent of the shorter output side
+    scale: number; // percent of the output width
+    opacity: number; // 0-100
+    tiled: boolean;
+}
+
 export enum MetadataMode {
     StripAll = "stripAll",
     KeepOrientation = "keepOrientation",
@@ -102,10 +209,98 @@ export interface ProcessConfig {
     pngOptions: PngOptions;
     jpegOptions: JpegOptions;
     webpOptions: WebPOptions;
+    gifOptions?: GifOptions;
+    svgOptions?: SvgOptions;
+    adjustments?: AdjustmentOptions;
+    matte?: MatteOptions;
+    watermark?: WatermarkOptions;
     metadataMode: MetadataMode;
     fileConflictMode: FileConflictMode;
     preserveFolderStructure: boolean;
     outputPath: string;
+    incremental?: boolean;
+    fixOutputExtension?: boolean; // name outputs after the format they hold
+    limits?: DecodeLimits;
+    inPlace?: boolean; // replace originals, backing them up to outputPath
+}
+
+// Checked against image headers before decoding; unset fields use the defaults
+export interface DecodeLimits {
+    maxWidth?: number;
+    maxHeight?: number;
+    maxPixels?: number;
+    maxAllocMb?: number;
+}
+
+// Structured error returned by every backend command
+export type ErrorCode =
+    | "NotFound"
+    | "PermissionDenied"
+    | "DecodeFailed"
+    | "UnsupportedFormat"
+    | "InvalidConfig"
+    | "EncodeFailed"
+    | "DiskFull"
+    | "IoFailed"
+    | "ProcessingFailed"
+    | "MetadataFailed"
+    | "LimitExceeded"
+    | "Internal";
+
+export interface AppError {
+    code: ErrorCode;
+    message: string;
+    path?: string;
+    details?: string;
+}
+
+// Format picked by OutputFormat.Auto
+export interface FormatChoice {
+    format: OutputFormat;
+    reason: string;
+}
+
+export interface CompressionPreview {
+    preview: string;
+    mimeType: string;
+    exact: boolean;
+    originalSize: number;
+    newSize: number;
+    compressionRatio: number;
+    width: number;
+    height: number;
+    format: OutputFormat;
+    formatChoice?: FormatChoice;
+    warnings: string[];
+    comparison?: ImageComparison | null; // when requested with `compare`
+}
+
+export interface ChannelError {
+    mean: number;
+    max: number;
+    rmse: number;
+}
+
+export interface ErrorRegion {
+    x: number;
+    y: number;
+    width: number;
+    height: number;
+    meanError: number;
+    maxError: number;
+}
+
+export interface ImageComparison {
+    heatmap: string;
+    width: number;
+    height: number;
+    resized: boolean;
+    red: ChannelError;
+    green: ChannelError;
+    blue: ChannelError;
+    alpha: ChannelError;
+    psnr?: number | null;
+    regions: ErrorRegion[];
 }
 
 export interface ProcessResult {
@@ -114,7 +309,31 @@ export interface ProcessResult {
     newSize: number;
     compressionRatio: number;
     outputPath: string;
-    error?: string;
+    error?: AppError;
+    cached?: boolean;
+    unchanged?: boolean; // in-place output wasn't smaller, original kept
+    warnings?: string[];
+    formatChoice?: FormatChoice;
+}
+
+// Scheduling for process_batch_images, passed next to the config
+export interface BatchOptions {
+    workers?: number; // 0 = one per CPU core
+    memoryLimitMb?: number; // estimated decoded memory of images in flight
+}
+
+export interface BatchSummary {
+    results: ProcessResult[];
+    processed: number;
+    cached: number;
+    failed: number;
+    stopped: boolean; // output disk filled up; remaining files were skipped
+}
+
+// Result of undoing an in-place run
+export interface UndoSummary {
+    restored: number;
+    errors: AppError[]; // files left as they are; undo can be retried
 }
 
 // UI State